@@ -0,0 +1,296 @@
+use gif::{Encoder, Frame, Repeat};
+use mushypeas::{Display, Palette, FRAME_RATE, SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+// Pictures are ten times the size of the low resolution display, whichever
+// frontend is drawing it
+const IMAGE_WIDTH: usize = SCREEN_WIDTH * 10;
+
+const IMAGE_HEIGHT: usize = SCREEN_HEIGHT * 10;
+
+/// Saves the display as a 640x320 PNG, in the palette's colours.
+pub fn screenshot(path: &str, display: &Display, palette: &Palette) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+
+    let mut encoder = png::Encoder::new(file, IMAGE_WIDTH as u32, IMAGE_HEIGHT as u32);
+
+    // Pixels are already indices into the palette, so no conversion is needed
+    encoder.set_color(png::ColorType::Indexed);
+
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder.set_palette(rgb(palette));
+
+    let mut writer = encoder.write_header()?;
+
+    writer.write_image_data(&scale(display))?;
+
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Captures the display once a frame, to an animated GIF or a stream of raw
+/// frames for tools like ffmpeg to encode.
+pub struct Recorder {
+    path: String,
+    video: Video,
+    frames: u64,
+}
+
+enum Video {
+    Gif {
+        encoder: Encoder<BufWriter<File>>,
+        // The picture on screen, which isn't written until it changes so
+        // that its delay covers every frame it was shown for
+        held: Option<(Vec<u8>, Palette, u64)>,
+    },
+    Raw(BufWriter<File>),
+}
+
+impl Recorder {
+    /// Starts a GIF if `path` ends in `.gif`, otherwise raw RGB24 frames at 60fps.
+    pub fn create(path: &str) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+
+        let video = if path.to_ascii_lowercase().ends_with(".gif") {
+            let mut encoder = Encoder::new(file, IMAGE_WIDTH as u16, IMAGE_HEIGHT as u16, &[])
+                .map_err(io::Error::other)?;
+
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .map_err(io::Error::other)?;
+
+            Video::Gif {
+                encoder,
+                held: None,
+            }
+        } else {
+            Video::Raw(file)
+        };
+
+        Ok(Recorder {
+            path: path.to_string(),
+            video,
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Adds the display as it looks this frame.
+    pub fn frame(&mut self, display: &Display, palette: &Palette) -> io::Result<()> {
+        let pixels = scale(display);
+
+        match &mut self.video {
+            Video::Gif { encoder, held } => match held {
+                Some((last, last_palette, _)) if *last == pixels && last_palette == palette => {}
+                _ => {
+                    if let Some((last, last_palette, start)) = held.take() {
+                        write_gif_frame(encoder, last, &last_palette, start, self.frames)?;
+                    }
+
+                    *held = Some((pixels, *palette, self.frames));
+                }
+            },
+            Video::Raw(file) => {
+                let colours: Vec<_> = pixels
+                    .iter()
+                    .flat_map(|&pixel| bytes(palette.colour(pixel)))
+                    .collect();
+
+                file.write_all(&colours)?;
+            }
+        }
+
+        self.frames += 1;
+
+        Ok(())
+    }
+
+    /// Writes out anything still held back and closes the file, returning a
+    /// description of what was captured.
+    pub fn finish(self) -> io::Result<String> {
+        match self.video {
+            Video::Gif { mut encoder, held } => {
+                if let Some((last, palette, start)) = held {
+                    write_gif_frame(&mut encoder, last, &palette, start, self.frames)?;
+                }
+
+                encoder.into_inner()?.flush()?;
+
+                Ok(format!("{} frames", self.frames))
+            }
+            Video::Raw(mut file) => {
+                file.flush()?;
+
+                Ok(format!(
+                    "{} frames of {}x{} RGB24 at {}fps",
+                    self.frames, IMAGE_WIDTH, IMAGE_HEIGHT, FRAME_RATE
+                ))
+            }
+        }
+    }
+}
+
+// Writes a picture shown from frame `start` until `end`
+fn write_gif_frame(
+    encoder: &mut Encoder<BufWriter<File>>,
+    pixels: Vec<u8>,
+    palette: &Palette,
+    start: u64,
+    end: u64,
+) -> io::Result<()> {
+    // GIF delays are in hundredths of a second, so round each end to the
+    // nearest to keep the total in step with the frames
+    let centiseconds = |frame: u64| (frame * 100 + FRAME_RATE as u64 / 2) / FRAME_RATE as u64;
+
+    let mut frame =
+        Frame::from_indexed_pixels(IMAGE_WIDTH as u16, IMAGE_HEIGHT as u16, pixels, None);
+
+    frame.palette = Some(rgb(palette));
+
+    frame.delay = (centiseconds(end) - centiseconds(start)).min(u16::MAX as u64) as u16;
+
+    encoder.write_frame(&frame).map_err(io::Error::other)
+}
+
+// Stretches the display over the image, whichever resolution it's in
+fn scale(display: &Display) -> Vec<u8> {
+    let pixels = display.pixels();
+
+    let (scale_x, scale_y) = (
+        IMAGE_WIDTH / display.width(),
+        IMAGE_HEIGHT / display.height(),
+    );
+
+    (0..IMAGE_WIDTH * IMAGE_HEIGHT)
+        .map(|i| {
+            let (x, y) = (i % IMAGE_WIDTH / scale_x, i / IMAGE_WIDTH / scale_y);
+
+            pixels[y * display.width() + x]
+        })
+        .collect()
+}
+
+// The palette as the RGB triples image formats expect
+fn rgb(palette: &Palette) -> Vec<u8> {
+    palette
+        .colours()
+        .iter()
+        .flat_map(|&colour| bytes(colour))
+        .collect()
+}
+
+fn bytes(colour: u32) -> [u8; 3] {
+    [(colour >> 16) as u8, (colour >> 8) as u8, colour as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mushypeas::{Machine, Theme};
+    use std::{env, fs, path::PathBuf};
+
+    // A display with the top left pixel lit, and the same after it's cleared
+    fn displays() -> (Display, Display) {
+        let mut machine = Machine::new();
+
+        let rom = mushypeas::assemble("i := dot\nsprite v0 v0 1\nclear\nexit\n: dot 0x80").unwrap();
+
+        machine.load_rom(&rom).unwrap();
+
+        // The display wait quirk leaves the draw on its own frame
+        machine.run_frame().unwrap();
+
+        let lit = machine.display().clone();
+
+        machine.run_frame().unwrap();
+
+        (lit, machine.display().clone())
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("mushypeas-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn screenshots_are_indexed_pngs() {
+        let (display, _) = displays();
+
+        let palette = Theme::Green.palette();
+
+        let path = temp_path("screenshot.png");
+
+        screenshot(path.to_str().unwrap(), &display, &palette).unwrap();
+
+        let mut decoder = png::Decoder::new(File::open(&path).unwrap());
+
+        decoder.set_transformations(png::Transformations::IDENTITY);
+
+        let mut reader = decoder.read_info().unwrap();
+
+        assert_eq!(reader.info().palette.as_deref(), Some(&rgb(&palette)[..]));
+
+        let mut pixels = vec![0; reader.output_buffer_size()];
+
+        let info = reader.next_frame(&mut pixels).unwrap();
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((info.width, info.height), (640, 320));
+
+        // The lit pixel is scaled up to a 10x10 block
+        assert_eq!(&pixels[..11], &[1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0]);
+
+        assert_eq!(pixels[9 * IMAGE_WIDTH], 1);
+
+        assert_eq!(pixels[10 * IMAGE_WIDTH], 0);
+
+        assert_eq!(pixels.iter().filter(|&&pixel| pixel == 1).count(), 100);
+    }
+
+    #[test]
+    fn gifs_hold_each_picture_for_as_long_as_it_was_shown() {
+        let (lit, cleared) = displays();
+
+        let palette = Theme::Classic.palette();
+
+        let path = temp_path("video.gif");
+
+        let mut recorder = Recorder::create(path.to_str().unwrap()).unwrap();
+
+        for display in [&lit, &lit, &lit, &cleared, &cleared] {
+            recorder.frame(display, &palette).unwrap();
+        }
+
+        assert_eq!(recorder.finish().unwrap(), "5 frames");
+
+        let mut options = gif::DecodeOptions::new();
+
+        options.set_color_output(gif::ColorOutput::Indexed);
+
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+
+        let mut frames = Vec::new();
+
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer[0], frame.palette.clone()));
+        }
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((decoder.width(), decoder.height()), (640, 320));
+
+        // Three frames then two, in hundredths of a second that add up to the 8 shown in all
+        assert_eq!(
+            frames,
+            [(5, 1, Some(rgb(&palette))), (3, 0, Some(rgb(&palette)))]
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use mushypeas::{
+    EmulatorError, Machine, Palette, Platform, Rng, RngAlgorithm, Syntax, Theme,
+    DEFAULT_CYCLES_PER_FRAME, DEFAULT_FREQUENCY, DEFAULT_REWIND_FRAMES, DEFAULT_VOLUME, FRAME_RATE,
+};
+
+use clap::{CommandFactory, ErrorKind, Parser, Subcommand};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zip::ZipArchive;
+
+#[derive(Clone, Debug, Parser)]
+#[clap(name = "mushypeas", subcommand_negates_reqs = true)]
+pub struct Config {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    #[clap(short, long)]
+    pub mute: bool,
+
+    /// Beep volume, from 0 to 1
+    #[clap(long, default_value_t = DEFAULT_VOLUME)]
+    pub volume: f32,
+
+    /// Beep frequency in Hz
+    #[clap(long, default_value_t = DEFAULT_FREQUENCY)]
+    pub frequency: f32,
+
+    /// The ROM to run: a file, a .zip holding one .ch8, or - for stdin
+    #[clap(short, long, required = true)]
+    rom: Option<String>,
+
+    /// Instructions executed per 60Hz frame
+    #[clap(short, long, global = true, default_value_t = DEFAULT_CYCLES_PER_FRAME)]
+    pub cycles_per_frame: usize,
+
+    /// Start paused, with a debugger REPL on the terminal
+    #[clap(short, long)]
+    pub debug: bool,
+
+    /// Seconds of gameplay kept for rewinding with Backspace, or 0 to disable it
+    #[clap(long, default_value_t = DEFAULT_REWIND_FRAMES as u32 / FRAME_RATE)]
+    pub rewind: u32,
+
+    /// Seed for the random numbers from CXKK, which otherwise differ every run
+    #[clap(long, global = true)]
+    pub seed: Option<u64>,
+
+    /// Random number generator for CXKK: xorshift or vip
+    #[clap(long, global = true, default_value_t = RngAlgorithm::default())]
+    pub rng: RngAlgorithm,
+
+    /// Record the keys pressed on every frame to a movie file
+    #[clap(long, conflicts_with = "play")]
+    pub record: Option<String>,
+
+    /// Replay a movie file's keys instead of reading the keyboard
+    #[clap(long)]
+    pub play: Option<String>,
+
+    /// What to do when the program faults: halt, debug or ignore
+    #[clap(long, default_value_t = FaultPolicy::Halt)]
+    pub on_fault: FaultPolicy,
+
+    /// Key bindings file, instead of ~/.config/mushypeas/keymap.conf
+    #[clap(long)]
+    pub keymap: Option<String>,
+
+    /// Colour theme: classic, green, amber, lcd, octo or high-contrast
+    #[clap(long, global = true, default_value_t = Theme::default())]
+    pub theme: Theme,
+
+    /// Custom colours instead of a theme, as 2 or 4 hex colours: the background,
+    /// then plane 1, plane 2 and both planes, like #000000,#FFFFFF
+    #[clap(long, global = true)]
+    pub colours: Option<Palette>,
+
+    /// Save a PNG of the display to this file on exit
+    #[clap(long, global = true)]
+    pub screenshot: Option<String>,
+
+    /// Capture the display to this file: an animated GIF if it ends in .gif,
+    /// otherwise raw RGB24 frames at 60fps
+    #[clap(long)]
+    pub video: Option<String>,
+
+    /// Where to draw the display: window, or tui for the terminal
+    #[clap(long, global = true, default_value_t = FrontendKind::Window)]
+    pub frontend: FrontendKind,
+
+    /// Characters the terminal frontend draws with: half-block, or braille
+    /// for a smaller picture
+    #[clap(long, global = true, default_value_t = Glyphs::HalfBlock)]
+    pub glyphs: Glyphs,
+
+    /// Quirk preset to emulate: vip, chip48, schip or xochip
+    #[clap(short, long, global = true, default_value_t = Platform::default())]
+    pub platform: Platform,
+}
+
+/// How the frontend reacts to a CPU fault, such as a stack overflow.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FaultPolicy {
+    /// Stop the emulator
+    Halt,
+    /// Pause in the debugger at the faulting instruction
+    Debug,
+    /// Report it and carry on after the faulting instruction
+    Ignore,
+}
+
+impl fmt::Display for FaultPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultPolicy::Halt => f.write_str("halt"),
+            FaultPolicy::Debug => f.write_str("debug"),
+            FaultPolicy::Ignore => f.write_str("ignore"),
+        }
+    }
+}
+
+impl FromStr for FaultPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "halt" => Ok(FaultPolicy::Halt),
+            "debug" => Ok(FaultPolicy::Debug),
+            "ignore" => Ok(FaultPolicy::Ignore),
+            _ => Err(format!(
+                "unknown fault policy '{}', expected halt, debug or ignore",
+                s
+            )),
+        }
+    }
+}
+
+/// What the display is drawn to and the keyboard read from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrontendKind {
+    /// A desktop window
+    Window,
+    /// The terminal, for when there's no display server
+    Tui,
+}
+
+impl fmt::Display for FrontendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrontendKind::Window => f.write_str("window"),
+            FrontendKind::Tui => f.write_str("tui"),
+        }
+    }
+}
+
+impl FromStr for FrontendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "window" => Ok(FrontendKind::Window),
+            "tui" => Ok(FrontendKind::Tui),
+            _ => Err(format!("unknown frontend '{}', expected window or tui", s)),
+        }
+    }
+}
+
+/// How the terminal frontend draws pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Glyphs {
+    /// Two pixels a character, one above the other, each in its own colour
+    HalfBlock,
+    /// Eight pixels a character in a 2x4 grid, sharing one colour
+    Braille,
+}
+
+impl fmt::Display for Glyphs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Glyphs::HalfBlock => f.write_str("half-block"),
+            Glyphs::Braille => f.write_str("braille"),
+        }
+    }
+}
+
+impl FromStr for Glyphs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "half-block" => Ok(Glyphs::HalfBlock),
+            "braille" => Ok(Glyphs::Braille),
+            _ => Err(format!(
+                "unknown glyphs '{}', expected half-block or braille",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Print a ROM's disassembly
+    Disasm {
+        rom: String,
+
+        /// Mnemonics to use: octo or cowgod
+        #[clap(short, long, default_value_t = Syntax::Octo)]
+        syntax: Syntax,
+    },
+
+    /// Assemble Octo source into a ROM
+    Asm {
+        source: String,
+
+        /// Where to write the ROM
+        #[clap(short, long)]
+        output: String,
+    },
+
+    /// Run a ROM, in a window or headless
+    Run {
+        /// The ROM to run: a file, a .zip holding one .ch8, or - for stdin
+        rom: String,
+
+        /// Run without a window and print the final state as JSON
+        #[clap(long, requires = "cycles")]
+        headless: bool,
+
+        /// Instructions to execute when headless
+        #[clap(long, requires = "headless")]
+        cycles: Option<u64>,
+
+        /// How to dump the display when headless: ascii or pbm
+        #[clap(long, default_value_t = DisplayFormat::Ascii)]
+        display: DisplayFormat,
+    },
+}
+
+/// How the headless runner writes out the display.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DisplayFormat {
+    /// One string per row, with a character per pixel
+    Ascii,
+    /// A plain PBM image, with any lit plane counting as black
+    Pbm,
+}
+
+impl fmt::Display for DisplayFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayFormat::Ascii => f.write_str("ascii"),
+            DisplayFormat::Pbm => f.write_str("pbm"),
+        }
+    }
+}
+
+impl FromStr for DisplayFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ascii" => Ok(DisplayFormat::Ascii),
+            "pbm" => Ok(DisplayFormat::Pbm),
+            _ => Err(format!(
+                "unknown display format '{}', expected ascii or pbm",
+                s
+            )),
+        }
+    }
+}
+
+impl Config {
+    /// Parses the command line, exiting with usage if it's invalid or asks for
+    /// options that can't be used together.
+    pub fn from_args() -> Self {
+        let config = Config::parse();
+
+        // The debugger console reads lines from stdin, which the terminal frontend
+        // has in raw mode for its keys, so the two would fight over every byte
+        let debugger = config.debug || config.on_fault == FaultPolicy::Debug;
+
+        if config.frontend == FrontendKind::Tui && debugger {
+            Config::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "the debugger can't share the terminal with --frontend tui",
+                )
+                .exit();
+        }
+
+        config
+    }
+
+    /// Reads the ROM, refusing it if it doesn't fit in the platform's memory.
+    pub fn load_rom(&self) -> mushypeas::Result<Vec<u8>> {
+        read_rom(self.rom_path(), self.platform.max_rom_size())
+    }
+
+    /// The random number generator to use, seeded from the clock if there's no --seed.
+    pub fn rng(&self) -> Rng {
+        let seed = self.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos() as u64)
+        });
+
+        Rng::new(seed, self.rng)
+    }
+
+    /// A machine set up with the platform, speed and RNG asked for.
+    pub fn machine(&self) -> Machine {
+        let mut machine = Machine::new();
+
+        machine.set_cycles_per_frame(self.cycles_per_frame);
+
+        machine.set_quirks(self.platform.quirks());
+
+        machine.set_memory_size(self.platform.memory_size());
+
+        machine.cpu_mut().set_rng(self.rng());
+
+        machine
+    }
+
+    pub fn rom_path(&self) -> &str {
+        // Only a subcommand can stand in for --rom, and only run takes a ROM to run
+        match &self.command {
+            Some(Command::Run { rom, .. }) => rom,
+            _ => self.rom.as_deref().expect("--rom is required"),
+        }
+    }
+}
+
+/// Reads a whole ROM from a file, from stdin when `path` is `-`, or from the
+/// single `.ch8` inside a `.zip` archive, failing if it's over `max` bytes.
+pub fn read_rom(path: &str, max: usize) -> mushypeas::Result<Vec<u8>> {
+    if path == "-" {
+        return read_limited(io::stdin().lock(), max);
+    }
+
+    if path.to_ascii_lowercase().ends_with(".zip") {
+        return read_zip(path, max);
+    }
+
+    let file = File::open(path)?;
+
+    let size = file.metadata()?.len() as usize;
+
+    if size > max {
+        return Err(EmulatorError::RomTooLarge { size, max });
+    }
+
+    read_limited(file, max)
+}
+
+fn read_zip(path: &str, max: usize) -> mushypeas::Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(File::open(path)?).map_err(io::Error::from)?;
+
+    let roms: Vec<_> = archive
+        .file_names()
+        .filter(|name| name.to_ascii_lowercase().ends_with(".ch8"))
+        .map(String::from)
+        .collect();
+
+    let name = match roms.as_slice() {
+        [name] => name,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} holds {} .ch8 files, expected one", path, roms.len()),
+            )
+            .into())
+        }
+    };
+
+    let entry = archive.by_name(name).map_err(io::Error::from)?;
+
+    let size = entry.size() as usize;
+
+    if size > max {
+        return Err(EmulatorError::RomTooLarge { size, max });
+    }
+
+    // The size comes from the archive, which might not be telling the truth
+    let rom = read_limited(entry, max)?;
+
+    Ok(rom)
+}
+
+// Holds no more than a byte past `max` in memory, so a huge pipe or zip bomb
+// is only counted through to find its size rather than read in
+fn read_limited(mut reader: impl Read, max: usize) -> mushypeas::Result<Vec<u8>> {
+    let mut rom = Vec::new();
+
+    reader.by_ref().take(max as u64 + 1).read_to_end(&mut rom)?;
+
+    if rom.len() > max {
+        let rest = io::copy(&mut reader, &mut io::sink())? as usize;
+
+        return Err(EmulatorError::RomTooLarge {
+            size: rom.len() + rest,
+            max,
+        });
+    }
+
+    Ok(rom)
+}
@@ -0,0 +1,157 @@
+use mushypeas::Cpu;
+use std::{
+    io::{self, BufRead, Write},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+const HELP: &str = "\
+Commands:
+  c, continue        resume execution
+  p, pause           pause execution
+  s, step [n]        execute n instructions (default 1)
+  n, next            step, running over subroutine calls
+  b, break <addr>    set a breakpoint
+  d, delete <addr>   remove a breakpoint
+  l, list            list breakpoints
+  i, info            show the CPU state
+  h, help            show this message";
+
+#[derive(Copy, Clone, Debug)]
+pub enum Command {
+    Continue,
+    Pause,
+    Step(usize),
+    Next,
+    Break(usize),
+    Delete(usize),
+    List,
+    Info,
+    Help,
+}
+
+/// A debugger REPL reading commands from the terminal on its own thread,
+/// so the window keeps running while it waits for input.
+pub struct Console {
+    commands: Receiver<Command>,
+}
+
+impl Console {
+    pub fn spawn() -> Self {
+        let (sender, commands) = mpsc::channel();
+
+        thread::spawn(move || {
+            let stdin = io::stdin();
+
+            prompt();
+
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+
+                if !line.trim().is_empty() {
+                    match parse(&line) {
+                        Ok(command) if sender.send(command).is_err() => break,
+                        Ok(_) => {}
+                        Err(e) => println!("{}", e),
+                    }
+                }
+
+                prompt();
+            }
+        });
+
+        Console { commands }
+    }
+
+    pub fn try_recv(&self) -> Option<Command> {
+        self.commands.try_recv().ok()
+    }
+}
+
+pub fn print_help() {
+    println!("{}", HELP);
+}
+
+pub fn print_state(cpu: &Cpu) {
+    let instr = match cpu.decode_instr(cpu.pc()) {
+        Ok(instr) => instr.to_string(),
+        Err(e) => e.to_string(),
+    };
+
+    println!("PC: {:#05X}  {}", cpu.pc(), instr);
+
+    println!(
+        "I: {:#05X}  SP: {}  DT: {}  ST: {}",
+        cpu.index(),
+        cpu.sp(),
+        cpu.delay_timer(),
+        cpu.sound_timer()
+    );
+
+    for (row, regs) in cpu.registers().chunks(8).enumerate() {
+        let regs: Vec<_> = regs
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("V{:X}: {:02X}", row * 8 + i, value))
+            .collect();
+
+        println!("{}", regs.join("  "));
+    }
+
+    let stack: Vec<_> = cpu
+        .stack()
+        .iter()
+        .map(|addr| format!("{:#05X}", addr))
+        .collect();
+
+    println!("Stack: [{}]", stack.join(", "));
+}
+
+fn prompt() {
+    print!("(debug) ");
+
+    io::stdout().flush().ok();
+}
+
+fn parse(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+
+    let name = words.next().unwrap_or_default();
+
+    let arg = words.next();
+
+    let addr = || {
+        let arg = arg.ok_or_else(|| format!("'{}' needs an address", name))?;
+
+        parse_number(arg).ok_or_else(|| format!("Invalid address '{}'", arg))
+    };
+
+    match name {
+        "c" | "continue" => Ok(Command::Continue),
+        "p" | "pause" => Ok(Command::Pause),
+        "s" | "step" => match arg {
+            Some(count) => count
+                .parse()
+                .map(Command::Step)
+                .map_err(|_| format!("Invalid step count '{}'", count)),
+            None => Ok(Command::Step(1)),
+        },
+        "n" | "next" => Ok(Command::Next),
+        "b" | "break" => addr().map(Command::Break),
+        "d" | "delete" => addr().map(Command::Delete),
+        "l" | "list" => Ok(Command::List),
+        "i" | "info" => Ok(Command::Info),
+        "h" | "help" => Ok(Command::Help),
+        _ => Err(format!("Unknown command '{}', try 'help'", name)),
+    }
+}
+
+// Addresses are hex, with or without a 0x prefix, as that's how they're displayed
+fn parse_number(s: &str) -> Option<usize> {
+    let s = s.trim_start_matches("0x").trim_start_matches("0X");
+
+    usize::from_str_radix(s, 16).ok()
+}
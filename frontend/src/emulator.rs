@@ -0,0 +1,572 @@
+use crate::{
+    capture::{self, Recorder},
+    config::{Config, FaultPolicy},
+    console::{self, Command, Console},
+    frontend::Frontend,
+    input::{ButtonEvent, Gamepad},
+    keymap::{Bindings, Hotkey, Key, Keymap},
+};
+
+use mushypeas::{
+    Debugger, EmulatorError, Machine, Movie, Palette, Platform, RewindBuffer, Rng, Theme,
+    FRAME_RATE, NUM_KEYS,
+};
+use std::{fs, io, sync::mpsc::Receiver};
+
+// Save states are kept next to the ROM, one file per slot
+const NUM_SLOTS: usize = 10;
+
+/// Runs a ROM frame by frame on a frontend, with the hotkeys, debugger, rewinding
+/// and recording around it.
+pub struct Emulator<F: Frontend> {
+    machine: Machine,
+    platform: Platform,
+    debugger: Option<(Debugger, Console)>,
+    rewind: RewindBuffer,
+    frontend: F,
+    bindings: Bindings<Key>,
+    gamepad: Option<Gamepad<Receiver<ButtonEvent>>>,
+    paused: bool,
+    // The custom colours if there are any, then the themes
+    palettes: Vec<Palette>,
+    palette: usize,
+    redraw: bool,
+    rom: Vec<u8>,
+    rom_path: String,
+    slot: usize,
+    on_fault: FaultPolicy,
+    fault: Option<EmulatorError>,
+    // Cycles run so far, and where to stop
+    cycles: u64,
+    max_cycles: u64,
+    record_path: Option<String>,
+    recording: Option<Movie>,
+    play_path: Option<String>,
+    playback: Option<Movie>,
+    screenshot_path: Option<String>,
+    video_path: Option<String>,
+    video: Option<Recorder>,
+}
+
+impl<F: Frontend> Emulator<F> {
+    /// Sets up an emulator on `frontend`, with its keys and any gamepads' events
+    /// bound through `keymap`.
+    pub fn new(
+        config: Config,
+        frontend: F,
+        keymap: Keymap,
+        gamepads: Option<Receiver<ButtonEvent>>,
+    ) -> mushypeas::Result<Self> {
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+
+        let bindings = keymap.keyboard().map_err(invalid)?;
+
+        let gamepad_bindings = keymap.gamepad().map_err(invalid)?;
+
+        Ok(Emulator {
+            machine: config.machine(),
+            platform: config.platform,
+            debugger: Self::open_debugger(&config),
+            rewind: RewindBuffer::new((config.rewind * FRAME_RATE) as usize),
+            frontend,
+            bindings,
+            gamepad: gamepads.map(|events| Gamepad::new(events, gamepad_bindings)),
+            paused: false,
+            palettes: Self::palettes(&config),
+            palette: 0,
+            redraw: false,
+            rom: Vec::new(),
+            rom_path: config.rom_path().to_string(),
+            slot: 0,
+            on_fault: config.on_fault,
+            fault: None,
+            cycles: 0,
+            max_cycles: u64::MAX,
+            record_path: config.record.clone(),
+            recording: None,
+            play_path: config.play.clone(),
+            playback: None,
+            screenshot_path: config.screenshot.clone(),
+            video_path: config.video.clone(),
+            video: None,
+        })
+    }
+
+    /// Stops the run once `cycles` cycles have gone by, even part way through a frame.
+    pub fn set_max_cycles(&mut self, cycles: u64) {
+        self.max_cycles = cycles;
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// The number of cycles the machine has been run for, outside the debugger.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// The fault that halted the run, under the halt fault policy.
+    pub fn fault(&self) -> Option<&EmulatorError> {
+        self.fault.as_ref()
+    }
+
+    /// Runs the ROM until the frontend closes, the quit hotkey is pressed, the
+    /// program exits or faults, or the cycles run out.
+    pub fn run(&mut self, raw: &[u8]) -> mushypeas::Result {
+        self.machine.load_rom(raw)?;
+
+        self.rom = raw.to_vec();
+
+        // Movies replay from power on, so start them once the ROM is in
+        if let Some(path) = &self.play_path {
+            let movie = Movie::from_bytes(&fs::read(path)?)?;
+
+            movie.apply(&mut self.machine)?;
+
+            self.playback = Some(movie);
+        }
+
+        if self.record_path.is_some() {
+            self.recording = Some(Movie::new(&self.machine, self.platform));
+        }
+
+        if let Some(path) = &self.video_path {
+            self.video = Some(Recorder::create(path)?);
+        }
+
+        if self.debugger.is_some() {
+            println!("Paused, type 'help' for debugger commands");
+
+            console::print_state(self.machine.cpu());
+        }
+
+        let mut result = Ok(());
+
+        while self.frontend.is_open()
+            && !self.hotkey_down(Hotkey::Quit)
+            && !self.machine.cpu().halted()
+            && self.fault.is_none()
+            && self.cycles < self.max_cycles
+        {
+            self.update();
+
+            self.hotkeys();
+
+            self.debug();
+
+            let frame = self.machine.frames();
+
+            let running = if self.hotkey_down(Hotkey::Rewind) {
+                // Holding the rewind key steps back a frame at a time instead of running
+                self.rewind.rewind(&mut self.machine);
+
+                false
+            } else if self.paused {
+                false
+            } else {
+                self.run_frame()
+            };
+
+            if running {
+                self.rewind.push(&self.machine);
+
+                if let Some(movie) = &mut self.recording {
+                    movie.record(frame, self.machine.cpu().keys());
+                }
+            }
+
+            // Let the buzzer fall silent rather than hold a note while paused
+            if running {
+                self.frontend.play(self.machine.cpu());
+            }
+
+            // Stop on a frontend error, but still save what was recorded
+            if let Err(e) = self.draw() {
+                result = Err(e.into());
+
+                break;
+            }
+        }
+
+        if let (Some(path), Some(movie)) = (&self.record_path, &self.recording) {
+            fs::write(path, movie.to_bytes())?;
+
+            println!("Recorded {} frames to {}", movie.len(), path);
+        }
+
+        self.stop_video();
+
+        if let Some(path) = &self.screenshot_path {
+            capture::screenshot(path, self.machine.display(), &self.palettes[self.palette])?;
+        }
+
+        result
+    }
+
+    // Runs a frame unless the debugger is paused, returning whether it ran
+    fn run_frame(&mut self) -> bool {
+        let frame = self.machine.frames();
+
+        loop {
+            let result = match &mut self.debugger {
+                Some((debugger, _)) if debugger.is_paused() => return false,
+                Some((debugger, _)) => debugger.run_frame(&mut self.machine).map(|stopped| {
+                    if stopped {
+                        println!("Paused at {:#05X}", self.machine.cpu().pc());
+
+                        console::print_state(self.machine.cpu());
+                    }
+                }),
+                None => self.run_cycles(),
+            };
+
+            let e = match result {
+                Ok(()) => return true,
+                Err(e) => e,
+            };
+
+            let ignored = self.on_fault == FaultPolicy::Ignore;
+
+            self.handle_fault(e);
+
+            // Carry on past an ignored fault within the same frame, so the loop
+            // still runs one frame per frame
+            if !ignored || self.machine.frames() != frame {
+                return true;
+            }
+        }
+    }
+
+    // Runs the rest of the frame, stopping early if the program exits or the
+    // cycles run out. Running again after a fault finishes the same frame.
+    fn run_cycles(&mut self) -> mushypeas::Result {
+        let frame = self.machine.frames();
+
+        while self.machine.frames() == frame
+            && !self.machine.cpu().halted()
+            && self.cycles < self.max_cycles
+        {
+            self.cycles += 1;
+
+            self.machine.step()?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_fault(&mut self, e: EmulatorError) {
+        match self.on_fault {
+            FaultPolicy::Halt => {
+                eprintln!("Halted: {}", e);
+
+                self.fault = Some(e);
+            }
+            FaultPolicy::Debug => {
+                let (debugger, _) = self.debugger.get_or_insert_with(|| {
+                    println!("Type 'help' for debugger commands");
+
+                    (Debugger::new(), Console::spawn())
+                });
+
+                debugger.pause();
+
+                println!("Paused on fault: {}", e);
+
+                console::print_state(self.machine.cpu());
+            }
+            FaultPolicy::Ignore => {
+                eprintln!("Ignoring fault: {}", e);
+
+                self.machine.cpu_mut().advance();
+            }
+        }
+    }
+
+    fn palettes(config: &Config) -> Vec<Palette> {
+        let mut palettes: Vec<_> = Theme::ALL.iter().map(|theme| theme.palette()).collect();
+
+        // Start on the chosen theme, keeping the rest in order after it
+        let start = Theme::ALL
+            .iter()
+            .position(|&t| t == config.theme)
+            .unwrap_or(0);
+
+        palettes.rotate_left(start);
+
+        if let Some(colours) = config.colours {
+            palettes.insert(0, colours);
+        }
+
+        palettes
+    }
+
+    fn open_debugger(config: &Config) -> Option<(Debugger, Console)> {
+        if !config.debug {
+            return None;
+        }
+
+        let mut debugger = Debugger::new();
+
+        debugger.pause();
+
+        Some((debugger, Console::spawn()))
+    }
+
+    // Carries out any commands typed into the debugger console since the last frame
+    fn debug(&mut self) {
+        let (debugger, console) = match &mut self.debugger {
+            Some(debug) => debug,
+            None => return,
+        };
+
+        while let Some(command) = console.try_recv() {
+            match command {
+                Command::Continue => debugger.resume(),
+                Command::Pause => {
+                    debugger.pause();
+
+                    console::print_state(self.machine.cpu());
+                }
+                Command::Step(_) | Command::Next if !debugger.is_paused() => {
+                    println!("Pause before stepping");
+                }
+                Command::Step(count) => {
+                    for _ in 0..count {
+                        if let Err(e) = debugger.step(&mut self.machine) {
+                            println!("Fault: {}", e);
+
+                            break;
+                        }
+                    }
+
+                    console::print_state(self.machine.cpu());
+                }
+                Command::Next => {
+                    if let Err(e) = debugger.step_over(&mut self.machine) {
+                        println!("Fault: {}", e);
+                    }
+
+                    if debugger.is_paused() {
+                        console::print_state(self.machine.cpu());
+                    }
+                }
+                Command::Break(addr) => debugger.add_breakpoint(addr),
+                Command::Delete(addr) => {
+                    if !debugger.remove_breakpoint(addr) {
+                        println!("No breakpoint at {:#05X}", addr);
+                    }
+                }
+                Command::List => {
+                    for addr in debugger.breakpoints() {
+                        println!("{:#05X}", addr);
+                    }
+                }
+                Command::Info => console::print_state(self.machine.cpu()),
+                Command::Help => console::print_help(),
+            }
+        }
+    }
+
+    // Carries out the hotkeys pressed this frame, apart from rewind and quit
+    // which act while held
+    fn hotkeys(&mut self) {
+        let frontend = &self.frontend;
+
+        let mut pressed: Vec<_> = self
+            .bindings
+            .hotkeys
+            .iter()
+            .filter(|(key, _)| frontend.is_key_pressed(*key))
+            .map(|(_, hotkey)| *hotkey)
+            .collect();
+
+        if let Some(gamepad) = &self.gamepad {
+            pressed.extend(gamepad.pressed_hotkeys());
+        }
+
+        for hotkey in pressed {
+            match hotkey {
+                Hotkey::Pause => {
+                    self.paused = !self.paused;
+
+                    println!("{}", if self.paused { "Paused" } else { "Resumed" });
+                }
+                Hotkey::Reset => self.reset(),
+                Hotkey::Palette => {
+                    self.palette = (self.palette + 1) % self.palettes.len();
+
+                    self.redraw = true;
+
+                    let palette = self.palettes[self.palette];
+
+                    match Theme::ALL.iter().find(|theme| theme.palette() == palette) {
+                        Some(theme) => println!("Theme {}", theme),
+                        None => println!("Colours {}", palette),
+                    }
+                }
+                Hotkey::Screenshot => {
+                    let path = format!("{}.{}.png", self.rom_path, self.machine.frames());
+
+                    let palette = &self.palettes[self.palette];
+
+                    match capture::screenshot(&path, self.machine.display(), palette) {
+                        Ok(()) => println!("Saved screenshot to {}", path),
+                        Err(e) => eprintln!("Could not save screenshot to {}: {}", path, e),
+                    }
+                }
+                Hotkey::Video if self.video.is_some() => self.stop_video(),
+                Hotkey::Video => {
+                    let path = format!("{}.{}.gif", self.rom_path, self.machine.frames());
+
+                    match Recorder::create(&path) {
+                        Ok(video) => {
+                            println!("Capturing video to {}", path);
+
+                            self.video = Some(video);
+                        }
+                        Err(e) => eprintln!("Could not capture video to {}: {}", path, e),
+                    }
+                }
+                Hotkey::Save => match fs::write(self.state_path(), self.machine.save_state()) {
+                    Ok(()) => println!("Saved to slot {}", self.slot),
+                    Err(e) => eprintln!("Could not save to slot {}: {}", self.slot, e),
+                },
+                Hotkey::Load => {
+                    let loaded = fs::read(self.state_path())
+                        .map_err(Into::into)
+                        .and_then(|state| self.machine.load_state(&state));
+
+                    match loaded {
+                        Ok(()) => println!("Loaded slot {}", self.slot),
+                        Err(e) => eprintln!("Could not load slot {}: {}", self.slot, e),
+                    }
+                }
+                Hotkey::PrevSlot => {
+                    self.slot = (self.slot + NUM_SLOTS - 1) % NUM_SLOTS;
+
+                    println!("Save slot {}", self.slot);
+                }
+                Hotkey::NextSlot => {
+                    self.slot = (self.slot + 1) % NUM_SLOTS;
+
+                    println!("Save slot {}", self.slot);
+                }
+                Hotkey::Rewind | Hotkey::Quit => {}
+            }
+        }
+    }
+
+    fn hotkey_down(&self, hotkey: Hotkey) -> bool {
+        self.bindings
+            .hotkeys
+            .iter()
+            .any(|(key, bound)| *bound == hotkey && self.frontend.is_key_down(*key))
+            || self
+                .gamepad
+                .as_ref()
+                .is_some_and(|gamepad| gamepad.hotkey_down(hotkey))
+    }
+
+    // Powers the machine back on with the same ROM, settings and seed
+    fn reset(&mut self) {
+        let rng = self.machine.cpu().rng();
+
+        let mut machine = Machine::new();
+
+        machine.set_cycles_per_frame(self.machine.cycles_per_frame());
+
+        machine.set_quirks(self.machine.quirks());
+
+        machine.set_memory_size(self.machine.memory_size());
+
+        machine
+            .cpu_mut()
+            .set_rng(Rng::new(rng.seed(), rng.algorithm()));
+
+        // The ROM fitted before, so it still fits
+        machine.load_rom(&self.rom).expect("The ROM loaded before");
+
+        self.machine = machine;
+
+        self.rewind.clear();
+
+        println!("Reset");
+    }
+
+    fn stop_video(&mut self) {
+        if let Some(video) = self.video.take() {
+            let path = video.path().to_string();
+
+            match video.finish() {
+                Ok(captured) => println!("Captured {} to {}", captured, path),
+                Err(e) => eprintln!("Could not finish video {}: {}", path, e),
+            }
+        }
+    }
+
+    fn state_path(&self) -> String {
+        // A ROM piped in has no file to keep its states next to, so they're named after its hash
+        if self.rom_path == "-" {
+            return format!("{:016x}.state{}", self.machine.rom_hash(), self.slot);
+        }
+
+        format!("{}.state{}", self.rom_path, self.slot)
+    }
+
+    fn update(&mut self) {
+        if let Some(gamepad) = &mut self.gamepad {
+            gamepad.update();
+        }
+
+        if let Some(movie) = &self.playback {
+            match movie.keys(self.machine.frames()) {
+                Some(keys) => {
+                    self.machine.set_keys(&keys);
+
+                    return;
+                }
+                None => {
+                    println!("Movie finished, handing control back to the keyboard");
+
+                    self.playback = None;
+                }
+            }
+        }
+
+        let mut keys = [false; NUM_KEYS];
+
+        for (key, i) in &self.bindings.keys {
+            keys[*i] |= self.frontend.is_key_down(*key);
+        }
+
+        if let Some(gamepad) = &self.gamepad {
+            gamepad.press_keys(&mut keys);
+        }
+
+        self.machine.set_keys(&keys);
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let rerender = self.machine.cpu_mut().take_rerender();
+
+        // A new palette needs drawing even if the display hasn't changed
+        if rerender || std::mem::take(&mut self.redraw) {
+            self.frontend
+                .present(self.machine.display(), &self.palettes[self.palette])?;
+        }
+
+        // Every frame goes in, so held pictures last as long in the video
+        if let Some(video) = &mut self.video {
+            let palette = &self.palettes[self.palette];
+
+            if let Err(e) = video.frame(self.machine.display(), palette) {
+                eprintln!("Could not capture video to {}: {}", video.path(), e);
+
+                self.video = None;
+            }
+        }
+
+        self.frontend.poll()
+    }
+}
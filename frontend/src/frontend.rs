@@ -0,0 +1,28 @@
+use crate::keymap::Key;
+use mushypeas::{Cpu, Display, Palette};
+use std::io;
+
+/// Where the emulator shows the display, reads the keyboard from and plays the
+/// buzzer, such as a window, the terminal or nowhere at all.
+///
+/// Each frame the emulator checks the keys, runs the machine, plays the frame's
+/// sound, presents the display if it changed, then polls. An error presenting
+/// or polling ends the run.
+pub trait Frontend {
+    /// False once the user has asked to quit, such as by closing the window.
+    fn is_open(&self) -> bool;
+
+    fn is_key_down(&self, key: Key) -> bool;
+
+    /// Whether `key` went down before the last poll, ignoring key repeat.
+    fn is_key_pressed(&self, key: Key) -> bool;
+
+    /// Shows a new picture of the display, in place of the last one.
+    fn present(&mut self, display: &Display, palette: &Palette) -> io::Result<()>;
+
+    /// Sounds the buzzer for a frame the machine has just run.
+    fn play(&mut self, cpu: &Cpu);
+
+    /// Reads the input since the last poll and waits out the rest of the frame.
+    fn poll(&mut self) -> io::Result<()>;
+}
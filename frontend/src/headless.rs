@@ -0,0 +1,194 @@
+use crate::{
+    config::{Config, DisplayFormat},
+    emulator::Emulator,
+    frontend::Frontend,
+    keymap::{Key, Keymap},
+};
+use mushypeas::{Cpu, Display, Machine, Palette};
+use std::io;
+
+/// A frontend with nothing to show and nobody at the keyboard, which runs as
+/// fast as it can, for headless runs and tests.
+///
+/// Keys can be scripted to go down and up on the frames given, counted in polls.
+#[derive(Clone, Debug, Default)]
+pub struct Headless {
+    frame: u64,
+    // Each frame a key goes down or up on, in order of frame
+    script: Vec<(u64, Key, bool)>,
+}
+
+impl Headless {
+    pub fn new() -> Self {
+        Headless::default()
+    }
+
+    /// Holds `key` down from `frame` until it's released.
+    pub fn press(&mut self, frame: u64, key: Key) {
+        self.add(frame, key, true);
+    }
+
+    pub fn release(&mut self, frame: u64, key: Key) {
+        self.add(frame, key, false);
+    }
+
+    fn add(&mut self, frame: u64, key: Key, down: bool) {
+        self.script.push((frame, key, down));
+
+        // A stable sort, so a key pressed and released on one frame ends up released
+        self.script.sort_by_key(|&(frame, _, _)| frame);
+    }
+}
+
+impl Frontend for Headless {
+    fn is_open(&self) -> bool {
+        true
+    }
+
+    fn is_key_down(&self, key: Key) -> bool {
+        // Whatever last happened to the key up to now
+        self.script
+            .iter()
+            .rev()
+            .find(|&&(frame, scripted, _)| scripted == key && frame <= self.frame)
+            .is_some_and(|&(_, _, down)| down)
+    }
+
+    fn is_key_pressed(&self, key: Key) -> bool {
+        self.script
+            .iter()
+            .any(|&(frame, scripted, down)| scripted == key && frame == self.frame && down)
+    }
+
+    fn present(&mut self, _display: &Display, _palette: &Palette) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn play(&mut self, _cpu: &Cpu) {}
+
+    fn poll(&mut self) -> io::Result<()> {
+        self.frame += 1;
+
+        Ok(())
+    }
+}
+
+/// Runs a ROM for up to `cycles` cycles on the headless frontend, then prints
+/// the machine's state as JSON. Returns false if the ROM faulted.
+pub fn run(
+    config: &Config,
+    raw: &[u8],
+    cycles: u64,
+    format: DisplayFormat,
+) -> mushypeas::Result<bool> {
+    let mut config = config.clone();
+
+    // Nobody can rewind, so don't spend time keeping frames for it
+    config.rewind = 0;
+
+    let mut emulator = Emulator::new(config, Headless::new(), Keymap::default(), None)?;
+
+    emulator.set_max_cycles(cycles);
+
+    emulator.run(raw)?;
+
+    let fault = emulator.fault().map(|e| e.to_string());
+
+    let json = to_json(
+        emulator.machine(),
+        emulator.cycles(),
+        fault.as_deref(),
+        format,
+    );
+
+    println!("{}", json);
+
+    Ok(fault.is_none())
+}
+
+fn to_json(machine: &Machine, cycles: u64, fault: Option<&str>, format: DisplayFormat) -> String {
+    let cpu = machine.cpu();
+
+    let list = |values: &mut dyn Iterator<Item = usize>| {
+        values
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    format!(
+        "{{
+  \"cycles\": {},
+  \"frames\": {},
+  \"halted\": {},
+  \"fault\": {},
+  \"pc\": {},
+  \"index\": {},
+  \"registers\": [{}],
+  \"stack\": [{}],
+  \"delay_timer\": {},
+  \"sound_timer\": {},
+  \"memory_hash\": \"{:016x}\",
+  \"display\": {}
+}}",
+        cycles,
+        machine.frames(),
+        cpu.halted(),
+        fault.map_or("null".to_string(), string),
+        cpu.pc(),
+        cpu.index(),
+        list(&mut cpu.registers().iter().map(|&v| v as usize)),
+        list(&mut cpu.stack().iter().copied()),
+        cpu.delay_timer(),
+        cpu.sound_timer(),
+        // A string, as JSON numbers can't hold all 64 bits exactly
+        mushypeas::rom_hash(cpu.memory()),
+        display(machine.display(), format),
+    )
+}
+
+fn display(display: &Display, format: DisplayFormat) -> String {
+    match format {
+        DisplayFormat::Ascii => {
+            let ascii = display.to_ascii();
+
+            let rows: Vec<_> = ascii
+                .lines()
+                .map(|row| format!("    {}", string(row)))
+                .collect();
+
+            format!("[\n{}\n  ]", rows.join(",\n"))
+        }
+        DisplayFormat::Pbm => {
+            let mut pbm = format!("P1\n{} {}\n", display.width(), display.height());
+
+            // Plain PBM allows the bits of a row without spaces between them
+            for row in display.pixels().chunks(display.width()) {
+                pbm.extend(row.iter().map(|&pixel| if pixel == 0 { '0' } else { '1' }));
+
+                pbm.push('\n');
+            }
+
+            string(&pbm)
+        }
+    }
+}
+
+// Quotes a string for JSON
+fn string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+
+    quoted
+}
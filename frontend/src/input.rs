@@ -0,0 +1,372 @@
+use crate::keymap::{Bindings, Hotkey};
+use mushypeas::NUM_KEYS;
+use std::sync::mpsc::Receiver;
+
+/// A gamepad button, named in keymap files with a `pad-` prefix, such as `pad-up`.
+///
+/// The face buttons are named by position, as controllers disagree on their labels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Button {
+    Up,
+    Down,
+    Left,
+    Right,
+    South,
+    East,
+    West,
+    North,
+    L,
+    R,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 12] = [
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+        Button::South,
+        Button::East,
+        Button::West,
+        Button::North,
+        Button::L,
+        Button::R,
+        Button::Select,
+        Button::Start,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::Up => "up",
+            Button::Down => "down",
+            Button::Left => "left",
+            Button::Right => "right",
+            Button::South => "south",
+            Button::East => "east",
+            Button::West => "west",
+            Button::North => "north",
+            Button::L => "l",
+            Button::R => "r",
+            Button::Select => "select",
+            Button::Start => "start",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Button::ALL
+            .iter()
+            .copied()
+            .find(|button| button.name().eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ButtonEvent {
+    pub button: Button,
+    pub pressed: bool,
+}
+
+/// Somewhere button events come from, such as the gamepads plugged in or a
+/// list of synthetic events.
+pub trait InputSource {
+    /// The next waiting event, or `None` once there are no more for now.
+    fn poll(&mut self) -> Option<ButtonEvent>;
+}
+
+impl InputSource for Receiver<ButtonEvent> {
+    fn poll(&mut self) -> Option<ButtonEvent> {
+        self.try_recv().ok()
+    }
+}
+
+/// The keys or buttons that went down since the last update, even those already
+/// released again, so that a tap shorter than a frame isn't missed.
+#[derive(Clone, Debug)]
+pub struct Presses<T>(Vec<T>);
+
+impl<T: PartialEq> Presses<T> {
+    pub fn new() -> Self {
+        Presses(Vec::new())
+    }
+
+    pub fn press(&mut self, pressed: T) {
+        if !self.0.contains(&pressed) {
+            self.0.push(pressed);
+        }
+    }
+
+    pub fn contains(&self, pressed: &T) -> bool {
+        self.0.contains(pressed)
+    }
+
+    /// Forgets the presses, ready for the next update.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+impl<T: PartialEq> Default for Presses<T> {
+    fn default() -> Self {
+        Presses::new()
+    }
+}
+
+/// Tracks which buttons of an input source are held, and maps them onto the
+/// keypad and hotkeys.
+pub struct Gamepad<S> {
+    source: S,
+    bindings: Bindings<Button>,
+    held: Vec<Button>,
+    pressed: Presses<Button>,
+}
+
+impl<S: InputSource> Gamepad<S> {
+    pub fn new(source: S, bindings: Bindings<Button>) -> Self {
+        Gamepad {
+            source,
+            bindings,
+            held: Vec::new(),
+            pressed: Presses::new(),
+        }
+    }
+
+    /// Takes every waiting event from the source, once a frame.
+    pub fn update(&mut self) {
+        self.pressed.clear();
+
+        while let Some(event) = self.source.poll() {
+            self.held.retain(|button| *button != event.button);
+
+            if event.pressed {
+                self.held.push(event.button);
+
+                self.pressed.press(event.button);
+            }
+        }
+    }
+
+    /// Presses the keypad keys bound to buttons held or tapped since the last
+    /// update, on top of those already set.
+    pub fn press_keys(&self, keys: &mut [bool; NUM_KEYS]) {
+        for (button, key) in &self.bindings.keys {
+            keys[*key] |= self.held.contains(button) || self.pressed.contains(button);
+        }
+    }
+
+    /// The hotkeys whose buttons went down during the last update.
+    pub fn pressed_hotkeys(&self) -> impl Iterator<Item = Hotkey> + '_ {
+        self.bindings
+            .hotkeys
+            .iter()
+            .filter(move |(button, _)| self.pressed.contains(button))
+            .map(|(_, hotkey)| *hotkey)
+    }
+
+    pub fn hotkey_down(&self, hotkey: Hotkey) -> bool {
+        self.bindings
+            .hotkeys
+            .iter()
+            .any(|(button, bound)| *bound == hotkey && self.held.contains(button))
+    }
+}
+
+/// Starts a thread reading each gamepad plugged in, or returns `None` if there aren't any.
+#[cfg(target_os = "linux")]
+pub fn open_gamepads() -> Option<Receiver<ButtonEvent>> {
+    use evdev::Key;
+    use std::{sync::mpsc, thread};
+
+    let (sender, events) = mpsc::channel();
+
+    let mut found = false;
+
+    for (_, mut device) in evdev::enumerate() {
+        let is_gamepad = device
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(Key::BTN_SOUTH));
+
+        if !is_gamepad {
+            continue;
+        }
+
+        println!("Using gamepad {}", device.name().unwrap_or("(unnamed)"));
+
+        found = true;
+
+        let sender = sender.clone();
+
+        // Reading blocks, so each device gets a thread which stops when unplugged
+        thread::spawn(move || {
+            while let Ok(events) = device.fetch_events() {
+                for event in events.flat_map(kernel::button_events) {
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    if found {
+        Some(events)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open_gamepads() -> Option<Receiver<ButtonEvent>> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+mod kernel {
+    use super::{Button, ButtonEvent};
+    use evdev::{AbsoluteAxisType, InputEvent, InputEventKind, Key};
+
+    // Turns a kernel input event into the button events it stands for
+    pub fn button_events(event: InputEvent) -> Vec<ButtonEvent> {
+        let press = |button, pressed| ButtonEvent { button, pressed };
+
+        // Most pads report their D-pad as a hat, -1 to 1 along each axis
+        let hat = |negative, positive| {
+            vec![
+                press(negative, event.value() < 0),
+                press(positive, event.value() > 0),
+            ]
+        };
+
+        match event.kind() {
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_HAT0X) => {
+                hat(Button::Left, Button::Right)
+            }
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_HAT0Y) => hat(Button::Up, Button::Down),
+            // Ignore auto-repeat, which has a value of 2
+            InputEventKind::Key(key) if event.value() < 2 => button(key)
+                .map(|button| vec![press(button, event.value() == 1)])
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn button(key: Key) -> Option<Button> {
+        let button = match key {
+            Key::BTN_DPAD_UP => Button::Up,
+            Key::BTN_DPAD_DOWN => Button::Down,
+            Key::BTN_DPAD_LEFT => Button::Left,
+            Key::BTN_DPAD_RIGHT => Button::Right,
+            Key::BTN_SOUTH => Button::South,
+            Key::BTN_EAST => Button::East,
+            Key::BTN_WEST => Button::West,
+            Key::BTN_NORTH => Button::North,
+            Key::BTN_TL => Button::L,
+            Key::BTN_TR => Button::R,
+            Key::BTN_SELECT => Button::Select,
+            Key::BTN_START => Button::Start,
+            _ => return None,
+        };
+
+        Some(button)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Sender};
+
+    fn gamepad() -> (Sender<ButtonEvent>, Gamepad<Receiver<ButtonEvent>>) {
+        let (sender, events) = mpsc::channel();
+
+        let bindings = Bindings {
+            keys: vec![(Button::South, 5), (Button::Up, 2)],
+            hotkeys: vec![(Button::Start, Hotkey::Pause), (Button::L, Hotkey::Rewind)],
+        };
+
+        (sender, Gamepad::new(events, bindings))
+    }
+
+    fn send(sender: &Sender<ButtonEvent>, button: Button, pressed: bool) {
+        sender.send(ButtonEvent { button, pressed }).unwrap();
+    }
+
+    fn keys<S: InputSource>(gamepad: &Gamepad<S>) -> [bool; NUM_KEYS] {
+        let mut keys = [false; NUM_KEYS];
+
+        gamepad.press_keys(&mut keys);
+
+        keys
+    }
+
+    #[test]
+    fn held_buttons_press_their_keys() {
+        let (sender, mut gamepad) = gamepad();
+
+        send(&sender, Button::South, true);
+
+        gamepad.update();
+
+        assert!(keys(&gamepad)[5]);
+
+        // Still held with nothing new from the source
+        gamepad.update();
+
+        assert!(keys(&gamepad)[5]);
+
+        send(&sender, Button::South, false);
+
+        gamepad.update();
+
+        assert_eq!(keys(&gamepad), [false; NUM_KEYS]);
+    }
+
+    #[test]
+    fn taps_between_updates_still_press_their_keys() {
+        let (sender, mut gamepad) = gamepad();
+
+        send(&sender, Button::Up, true);
+
+        send(&sender, Button::Up, false);
+
+        gamepad.update();
+
+        assert!(keys(&gamepad)[2]);
+
+        gamepad.update();
+
+        assert!(!keys(&gamepad)[2]);
+    }
+
+    #[test]
+    fn hotkeys_fire_once_but_stay_down_while_held() {
+        let (sender, mut gamepad) = gamepad();
+
+        send(&sender, Button::Start, true);
+
+        send(&sender, Button::L, true);
+
+        gamepad.update();
+
+        let pressed: Vec<_> = gamepad.pressed_hotkeys().collect();
+
+        assert_eq!(pressed, [Hotkey::Pause, Hotkey::Rewind]);
+
+        assert!(gamepad.hotkey_down(Hotkey::Rewind));
+
+        gamepad.update();
+
+        assert_eq!(gamepad.pressed_hotkeys().count(), 0);
+
+        assert!(gamepad.hotkey_down(Hotkey::Rewind));
+
+        assert!(!gamepad.hotkey_down(Hotkey::Quit));
+
+        send(&sender, Button::L, false);
+
+        gamepad.update();
+
+        assert!(!gamepad.hotkey_down(Hotkey::Rewind));
+    }
+}
@@ -0,0 +1,566 @@
+use crate::input::Button;
+use mushypeas::NUM_KEYS;
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+// Names starting with this are gamepad buttons rather than keyboard keys
+const GAMEPAD_PREFIX: &str = "pad-";
+
+// The layout of the COSMAC VIP's hex keypad on the left of a QWERTY keyboard,
+// with a gamepad's D-pad on the keys most games use for directions
+const DEFAULT_KEYMAP: &str = "\
+[default]
+1 = 1
+2 = 2 pad-up
+3 = 3
+c = 4
+
+4 = q pad-left
+5 = w pad-south
+6 = e pad-right
+d = r
+
+7 = a
+8 = s pad-down
+9 = d
+e = f
+
+a = z
+0 = x
+b = c
+f = v
+
+pause = p pad-start
+reset = f2 pad-select
+palette = f3
+screenshot = f12
+video = f10
+save = f5
+load = f9
+prev-slot = f6
+next-slot = f7
+rewind = backspace
+quit = escape
+";
+
+/// A key on the host's keyboard, whichever frontend reads it.
+///
+/// Keymap files name keys after these variants, ignoring case, such as `space`
+/// or `numpad0`, with digits also accepted bare.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Key {
+    Key0,
+    Key1,
+    Key2,
+    Key3,
+    Key4,
+    Key5,
+    Key6,
+    Key7,
+    Key8,
+    Key9,
+
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    F13,
+    F14,
+    F15,
+
+    Down,
+    Left,
+    Right,
+    Up,
+
+    Apostrophe,
+    Backquote,
+    Backslash,
+    Comma,
+    Equal,
+    LeftBracket,
+    Minus,
+    Period,
+    RightBracket,
+    Semicolon,
+    Slash,
+
+    Backspace,
+    Delete,
+    End,
+    Enter,
+    Escape,
+    Home,
+    Insert,
+    Menu,
+    PageDown,
+    PageUp,
+    Pause,
+    Space,
+    Tab,
+    NumLock,
+    CapsLock,
+    ScrollLock,
+
+    LeftShift,
+    RightShift,
+    LeftCtrl,
+    RightCtrl,
+    LeftAlt,
+    RightAlt,
+    LeftSuper,
+    RightSuper,
+
+    NumPad0,
+    NumPad1,
+    NumPad2,
+    NumPad3,
+    NumPad4,
+    NumPad5,
+    NumPad6,
+    NumPad7,
+    NumPad8,
+    NumPad9,
+    NumPadDot,
+    NumPadSlash,
+    NumPadAsterisk,
+    NumPadMinus,
+    NumPadPlus,
+    NumPadEnter,
+}
+
+impl Key {
+    #[rustfmt::skip]
+    pub const ALL: [Key; 106] = [
+        Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4,
+        Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+
+        Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I,
+        Key::J, Key::K, Key::L, Key::M, Key::N, Key::O, Key::P, Key::Q, Key::R,
+        Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+
+        Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8,
+        Key::F9, Key::F10, Key::F11, Key::F12, Key::F13, Key::F14, Key::F15,
+
+        Key::Down, Key::Left, Key::Right, Key::Up,
+
+        Key::Apostrophe, Key::Backquote, Key::Backslash, Key::Comma, Key::Equal,
+        Key::LeftBracket, Key::Minus, Key::Period, Key::RightBracket, Key::Semicolon,
+        Key::Slash,
+
+        Key::Backspace, Key::Delete, Key::End, Key::Enter, Key::Escape, Key::Home,
+        Key::Insert, Key::Menu, Key::PageDown, Key::PageUp, Key::Pause, Key::Space,
+        Key::Tab, Key::NumLock, Key::CapsLock, Key::ScrollLock,
+
+        Key::LeftShift, Key::RightShift, Key::LeftCtrl, Key::RightCtrl,
+        Key::LeftAlt, Key::RightAlt, Key::LeftSuper, Key::RightSuper,
+
+        Key::NumPad0, Key::NumPad1, Key::NumPad2, Key::NumPad3, Key::NumPad4,
+        Key::NumPad5, Key::NumPad6, Key::NumPad7, Key::NumPad8, Key::NumPad9,
+        Key::NumPadDot, Key::NumPadSlash, Key::NumPadAsterisk, Key::NumPadMinus,
+        Key::NumPadPlus, Key::NumPadEnter,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Key::ALL.iter().copied().find(|key| {
+            let key_name = format!("{:?}", key);
+
+            key_name.eq_ignore_ascii_case(name) || key_name.strip_prefix("Key") == Some(name)
+        })
+    }
+}
+
+/// Emulator actions which can be bound to host keys, alongside the keypad.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    Pause,
+    Reset,
+    Palette,
+    Screenshot,
+    Video,
+    Save,
+    Load,
+    PrevSlot,
+    NextSlot,
+    Rewind,
+    Quit,
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 11] = [
+        Hotkey::Pause,
+        Hotkey::Reset,
+        Hotkey::Palette,
+        Hotkey::Screenshot,
+        Hotkey::Video,
+        Hotkey::Save,
+        Hotkey::Load,
+        Hotkey::PrevSlot,
+        Hotkey::NextSlot,
+        Hotkey::Rewind,
+        Hotkey::Quit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hotkey::Pause => "pause",
+            Hotkey::Reset => "reset",
+            Hotkey::Palette => "palette",
+            Hotkey::Screenshot => "screenshot",
+            Hotkey::Video => "video",
+            Hotkey::Save => "save",
+            Hotkey::Load => "load",
+            Hotkey::PrevSlot => "prev-slot",
+            Hotkey::NextSlot => "next-slot",
+            Hotkey::Rewind => "rewind",
+            Hotkey::Quit => "quit",
+        }
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Which host keys, by name, press each CHIP-8 key and trigger each hotkey.
+///
+/// Bindings come from a file of `[default]` and `[<rom file name>]` sections,
+/// each line binding a hex key or hotkey to one or more host keys:
+///
+/// ```text
+/// [default]
+/// 5 = w up pad-south
+/// pause = p pad-start
+///
+/// [pong.ch8]
+/// 1 = up pad-up
+/// 4 = down pad-down
+/// ```
+///
+/// Names starting with `pad-` are gamepad buttons, the rest keyboard keys. The
+/// ROM's section only replaces the bindings it mentions.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    keys: Vec<Vec<String>>,
+    hotkeys: Vec<(Hotkey, Vec<String>)>,
+}
+
+/// A keymap with its key names looked up, for the keyboard or gamepads.
+#[derive(Clone, Debug)]
+pub struct Bindings<K> {
+    pub keys: Vec<(K, usize)>,
+    pub hotkeys: Vec<(K, Hotkey)>,
+}
+
+impl Keymap {
+    /// Loads the keymap for `rom` from `path`, or from the user's config
+    /// directory if that has one, falling back to the default layout.
+    pub fn load(path: Option<&str>, rom: &str) -> io::Result<Self> {
+        let keymap = Keymap::default();
+
+        let path = match path.map(PathBuf::from).or_else(default_path) {
+            Some(path) => path,
+            None => return Ok(keymap),
+        };
+
+        let text = fs::read_to_string(&path)?;
+
+        let rom = Path::new(rom)
+            .file_name()
+            .map_or(rom.into(), |name| name.to_string_lossy());
+
+        Keymap::parse(keymap, &text, &rom, &path.display().to_string())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Looks up every keyboard key name, failing on the first that isn't a [`Key`].
+    pub fn keyboard(&self) -> Result<Bindings<Key>, String> {
+        self.resolve(
+            |name| Some(name).filter(|name| !name.starts_with(GAMEPAD_PREFIX)),
+            Key::from_name,
+        )
+    }
+
+    pub fn gamepad(&self) -> Result<Bindings<Button>, String> {
+        self.resolve(|name| name.strip_prefix(GAMEPAD_PREFIX), Button::from_name)
+    }
+
+    // Looks up the names that `device` picks out, with any prefix removed
+    fn resolve<K>(
+        &self,
+        device: impl Fn(&str) -> Option<&str>,
+        lookup: impl Fn(&str) -> Option<K>,
+    ) -> Result<Bindings<K>, String> {
+        let key = |name: &String| match device(name) {
+            Some(bare) => lookup(bare)
+                .map(Some)
+                .ok_or_else(|| format!("unknown key '{}'", name)),
+            None => Ok(None),
+        };
+
+        let mut bindings = Bindings {
+            keys: Vec::new(),
+            hotkeys: Vec::new(),
+        };
+
+        for (chip8, names) in self.keys.iter().enumerate() {
+            for name in names {
+                if let Some(key) = key(name)? {
+                    bindings.keys.push((key, chip8));
+                }
+            }
+        }
+
+        for (hotkey, names) in &self.hotkeys {
+            for name in names {
+                if let Some(key) = key(name)? {
+                    bindings.hotkeys.push((key, *hotkey));
+                }
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    // Applies the default section then the ROM's on top of `keymap`, with later
+    // lines replacing earlier ones
+    fn parse(mut keymap: Keymap, text: &str, rom: &str, source: &str) -> Result<Self, String> {
+        let mut rom_bindings = Vec::new();
+
+        let mut section = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| format!("{}:{}: {}", source, i + 1, message);
+
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = Some(name.trim().to_string());
+
+                continue;
+            }
+
+            let (target, keys) = line.split_once('=').ok_or_else(|| {
+                error(format!("expected '<key> = <host keys>', found '{}'", line))
+            })?;
+
+            let target = target.trim();
+
+            let keys: Vec<_> = keys.split_whitespace().map(String::from).collect();
+
+            let binding = match (u8::from_str_radix(target, 16), hotkey(target)) {
+                (Ok(key), _) if target.len() == 1 => Binding::Key(key as usize),
+                (_, Some(hotkey)) => Binding::Hotkey(hotkey),
+                _ => return Err(error(format!("unknown CHIP-8 key or hotkey '{}'", target))),
+            };
+
+            match section.as_deref() {
+                Some("default") => keymap.bind(binding, keys),
+                Some(name) if name == rom => rom_bindings.push((binding, keys)),
+                Some(_) => {}
+                None => return Err(error("binding outside of a section".to_string())),
+            }
+        }
+
+        for (binding, keys) in rom_bindings {
+            keymap.bind(binding, keys);
+        }
+
+        Ok(keymap)
+    }
+
+    fn bind(&mut self, binding: Binding, keys: Vec<String>) {
+        match binding {
+            Binding::Key(key) => self.keys[key] = keys,
+            Binding::Hotkey(hotkey) => {
+                self.hotkeys.retain(|(bound, _)| *bound != hotkey);
+
+                self.hotkeys.push((hotkey, keys));
+            }
+        }
+    }
+}
+
+// The default layout, without looking for a keymap file
+impl Default for Keymap {
+    fn default() -> Self {
+        let keymap = Keymap {
+            keys: vec![Vec::new(); NUM_KEYS],
+            hotkeys: Vec::new(),
+        };
+
+        Keymap::parse(keymap, DEFAULT_KEYMAP, "", "default keymap")
+            .expect("The default keymap is valid")
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Binding {
+    Key(usize),
+    Hotkey(Hotkey),
+}
+
+fn hotkey(name: &str) -> Option<Hotkey> {
+    Hotkey::ALL
+        .iter()
+        .copied()
+        .find(|hotkey| hotkey.name().eq_ignore_ascii_case(name))
+}
+
+// $XDG_CONFIG_HOME/mushypeas/keymap.conf, or under ~/.config, if it exists
+fn default_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+    let path = config.join("mushypeas").join("keymap.conf");
+
+    if path.exists() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str, rom: &str) -> Result<Keymap, String> {
+        Keymap::parse(Keymap::default(), text, rom, "test.conf")
+    }
+
+    // The keyboard keys bound to a CHIP-8 key, in order
+    fn bound<K: Copy>(bindings: &Bindings<K>, chip8: usize) -> Vec<K> {
+        bindings
+            .keys
+            .iter()
+            .filter(|(_, key)| *key == chip8)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    const AZERTY: &str = "
+# Moves the keys under QWERTY's WASD to AZERTY's ZQSD
+[default]
+4 = a
+5 = z up
+7 = q
+a = w
+pause = p pad-start
+
+[pong.ch8]
+5 = i
+";
+
+    #[test]
+    fn rom_sections_override_the_default_section() {
+        let keyboard = parse(AZERTY, "pong.ch8").unwrap().keyboard().unwrap();
+
+        assert_eq!(bound(&keyboard, 5), [Key::I]);
+
+        assert_eq!(bound(&keyboard, 7), [Key::Q]);
+
+        let keyboard = parse(AZERTY, "tetris.ch8").unwrap().keyboard().unwrap();
+
+        assert_eq!(bound(&keyboard, 5), [Key::Z, Key::Up]);
+
+        // Keys the file doesn't mention keep the default layout
+        assert_eq!(bound(&keyboard, 8), [Key::S]);
+    }
+
+    #[test]
+    fn keys_are_split_between_the_keyboard_and_gamepads() {
+        let keymap = parse(AZERTY, "tetris.ch8").unwrap();
+
+        let keyboard = keymap.keyboard().unwrap();
+
+        let pause: Vec<_> = keyboard
+            .hotkeys
+            .iter()
+            .filter(|(_, hotkey)| *hotkey == Hotkey::Pause)
+            .map(|(key, _)| *key)
+            .collect();
+
+        assert_eq!(pause, [Key::P]);
+
+        let gamepad = keymap.gamepad().unwrap();
+
+        assert!(gamepad.hotkeys.contains(&(Button::Start, Hotkey::Pause)));
+    }
+
+    #[test]
+    fn unknown_key_names_are_refused_when_looked_up() {
+        let keymap = parse("[default]\n5 = nope\n6 = pad-nope", "").unwrap();
+
+        assert_eq!(keymap.keyboard().unwrap_err(), "unknown key 'nope'");
+
+        assert_eq!(keymap.gamepad().unwrap_err(), "unknown key 'pad-nope'");
+    }
+
+    #[test]
+    fn errors_give_the_file_and_line() {
+        let cases = [
+            ("5 = w", "test.conf:1: binding outside of a section"),
+            (
+                "[default]\n\n5 w",
+                "test.conf:3: expected '<key> = <host keys>', found '5 w'",
+            ),
+            (
+                "[default]\n# Not a key\n10 = w",
+                "test.conf:3: unknown CHIP-8 key or hotkey '10'",
+            ),
+            (
+                "[default]\nfast-forward = f",
+                "test.conf:2: unknown CHIP-8 key or hotkey 'fast-forward'",
+            ),
+        ];
+
+        for &(text, message) in &cases {
+            assert_eq!(parse(text, "").unwrap_err(), message);
+        }
+    }
+}
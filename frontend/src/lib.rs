@@ -0,0 +1,18 @@
+//! The desktop and terminal frontends for the interpreter, with the command
+//! line, keymaps, gamepads, sound and capture around them.
+//!
+//! [`Emulator`](emulator::Emulator) runs a ROM on any
+//! [`Frontend`](frontend::Frontend), including the [`Headless`](headless::Headless)
+//! one for running without a display or in tests.
+
+pub mod capture;
+pub mod config;
+pub mod console;
+pub mod emulator;
+pub mod frontend;
+pub mod headless;
+pub mod input;
+pub mod keymap;
+pub mod speaker;
+pub mod terminal;
+pub mod window;
@@ -0,0 +1,69 @@
+use mushypeas::MAX_INSTRS;
+use mushypeas_frontend::{
+    config::{self, Command, Config, FrontendKind},
+    emulator::Emulator,
+    headless, input,
+    keymap::Keymap,
+    terminal::Terminal,
+    window::Window,
+};
+
+fn main() -> mushypeas::Result {
+    let config = Config::from_args();
+
+    match &config.command {
+        Some(Command::Disasm { rom, syntax }) => {
+            let rom = config::read_rom(rom, MAX_INSTRS)?;
+
+            print!("{}", mushypeas::disassemble(&rom, *syntax));
+        }
+        Some(Command::Asm { source, output }) => {
+            let text = std::fs::read_to_string(source)?;
+
+            match mushypeas::assemble(&text) {
+                Ok(rom) => std::fs::write(output, rom)?,
+                Err(e) => {
+                    eprintln!("{}: {}", source, e);
+
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Command::Run {
+            headless: true,
+            cycles,
+            display,
+            ..
+        }) => {
+            let rom = config.load_rom()?;
+
+            let cycles = cycles.expect("--headless requires --cycles");
+
+            if !headless::run(&config, &rom, cycles, *display)? {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Run { .. }) | None => {
+            let rom = config.load_rom()?;
+
+            let keymap = Keymap::load(config.keymap.as_deref(), config.rom_path())?;
+
+            let gamepads = input::open_gamepads();
+
+            match config.frontend {
+                FrontendKind::Window => {
+                    let window = Window::open(&config)?;
+
+                    Emulator::new(config, window, keymap, gamepads)?.run(&rom)?;
+                }
+                FrontendKind::Tui => {
+                    let terminal = Terminal::open(&config)?;
+
+                    Emulator::new(config, terminal, keymap, gamepads)?.run(&rom)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use crate::config::Config;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SampleFormat, SizedSample, Stream, StreamConfig,
+};
+use mushypeas::{AudioSink, Beeper, FRAME_RATE};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+// Drop samples rather than let the queue drift more than a few frames behind the emulator
+const MAX_QUEUED_FRAMES: usize = 4;
+
+type SampleQueue = Arc<Mutex<VecDeque<f32>>>;
+
+/// Plays samples on the default output device.
+pub struct Speaker {
+    queue: SampleQueue,
+    sample_rate: u32,
+    _stream: Stream,
+}
+
+impl Speaker {
+    /// Opens the default output device, or returns `None` if there isn't a usable one.
+    pub fn open() -> Option<Self> {
+        let device = cpal::default_host().default_output_device()?;
+
+        let supported = device.default_output_config().ok()?;
+
+        let format = supported.sample_format();
+
+        let config: StreamConfig = supported.into();
+
+        let queue = SampleQueue::default();
+
+        let stream = match format {
+            SampleFormat::F32 => Speaker::build_stream::<f32>(&device, &config, &queue),
+            SampleFormat::I16 => Speaker::build_stream::<i16>(&device, &config, &queue),
+            SampleFormat::U16 => Speaker::build_stream::<u16>(&device, &config, &queue),
+            _ => None,
+        }?;
+
+        stream.play().ok()?;
+
+        Some(Speaker {
+            queue,
+            sample_rate: config.sample_rate.0,
+            _stream: stream,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn build_stream<T>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        queue: &SampleQueue,
+    ) -> Option<Stream>
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        let queue = Arc::clone(queue);
+
+        let channels = config.channels as usize;
+
+        device
+            .build_output_stream(
+                config,
+                move |data: &mut [T], _| {
+                    let mut queue = queue.lock().unwrap();
+
+                    // The emulator produces mono, so copy each sample to every channel
+                    for frame in data.chunks_mut(channels) {
+                        let sample = T::from_sample(queue.pop_front().unwrap_or(0.0));
+
+                        for channel in frame.iter_mut() {
+                            *channel = sample;
+                        }
+                    }
+                },
+                |e| eprintln!("Audio stream error: {}", e),
+                None,
+            )
+            .ok()
+    }
+}
+
+impl AudioSink for Speaker {
+    fn queue(&mut self, samples: &[f32]) {
+        let mut queue = self.queue.lock().unwrap();
+
+        queue.extend(samples);
+
+        let max = self.sample_rate as usize / FRAME_RATE as usize * MAX_QUEUED_FRAMES;
+
+        if queue.len() > max {
+            let excess = queue.len() - max;
+
+            queue.drain(..excess);
+        }
+    }
+}
+
+/// A beeper playing on the default output device with the volume and frequency
+/// asked for, or `None` if muted or there's no device.
+pub fn open_beeper(config: &Config) -> Option<Beeper<Speaker>> {
+    if config.mute {
+        return None;
+    }
+
+    let speaker = Speaker::open().or_else(|| {
+        eprintln!("No audio output device available, running without sound");
+
+        None
+    })?;
+
+    let sample_rate = speaker.sample_rate();
+
+    let mut beeper = Beeper::new(speaker, sample_rate);
+
+    beeper.set_volume(config.volume);
+
+    beeper.set_frequency(config.frequency);
+
+    Some(beeper)
+}
@@ -0,0 +1,334 @@
+use crate::{
+    config::{Config, Glyphs},
+    frontend::Frontend,
+    input::Presses,
+    keymap::Key,
+    speaker::{self, Speaker},
+};
+use crossterm::{
+    cursor::{Hide, MoveTo, MoveToNextLine, Show},
+    event::{
+        self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use mushypeas::{Beeper, Cpu, Display, Palette, FRAME_RATE};
+use std::{
+    io::{self, BufWriter, Stdout, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Most terminals only send key presses, repeating them while a key is held, so
+// a key counts as held until it's gone this long without another. Taps stay
+// short, at the cost of a held key letting go briefly before the repeats start.
+const KEY_HOLD: Duration = Duration::from_millis(200);
+
+/// Draws the display in the terminal with Unicode blocks or braille in 24 bit
+/// colour, and reads the keyboard through raw mode, for running without a
+/// display server.
+///
+/// Messages printed while it's open scroll beneath the picture.
+pub struct Terminal {
+    out: BufWriter<Stdout>,
+    glyphs: Glyphs,
+    open: bool,
+    // Whether the terminal reports key releases, so keys needn't time out
+    releases: bool,
+    // Each key held and when it was last seen down
+    held: Vec<(Key, Instant)>,
+    pressed: Presses<Key>,
+    next_frame: Instant,
+    // The height of the picture last drawn, or 0 to lay the terminal out again
+    rows: u16,
+    beeper: Option<Beeper<Speaker>>,
+}
+
+impl Terminal {
+    pub fn open(config: &Config) -> io::Result<Self> {
+        // Open the speaker first, so any complaint is seen before the screen is taken over
+        let beeper = speaker::open_beeper(config);
+
+        let mut out = BufWriter::new(io::stdout());
+
+        terminal::enable_raw_mode()?;
+
+        restore_newlines();
+
+        queue!(out, EnterAlternateScreen, Hide)?;
+
+        // Terminals speaking the kitty keyboard protocol can report releases
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+
+        if releases {
+            queue!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        out.flush()?;
+
+        Ok(Terminal {
+            out,
+            glyphs: config.glyphs,
+            open: true,
+            releases,
+            held: Vec::new(),
+            pressed: Presses::new(),
+            next_frame: Instant::now(),
+            rows: 0,
+            beeper,
+        })
+    }
+
+    fn read_input(&mut self) -> io::Result<()> {
+        while event::poll(Duration::ZERO)? {
+            let event = match event::read()? {
+                Event::Key(event) => event,
+                Event::Resize(..) => {
+                    self.rows = 0;
+
+                    continue;
+                }
+                _ => continue,
+            };
+
+            // Raw mode stops Ctrl+C interrupting, so it has to quit by hand
+            if event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL) {
+                self.open = false;
+            }
+
+            let key = match host_key(event.code) {
+                Some(key) => key,
+                None => continue,
+            };
+
+            let held = self.is_key_down(key);
+
+            self.held.retain(|(other, _)| *other != key);
+
+            if event.kind != KeyEventKind::Release {
+                if !held {
+                    self.pressed.press(key);
+                }
+
+                self.held.push((key, Instant::now()));
+            }
+        }
+
+        if !self.releases {
+            self.held.retain(|(_, seen)| seen.elapsed() < KEY_HOLD);
+        }
+
+        Ok(())
+    }
+
+    fn render(&mut self, display: &Display, palette: &Palette) -> io::Result<()> {
+        let (cell_width, cell_height) = match self.glyphs {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4),
+        };
+
+        let (columns, rows) = (display.width() / cell_width, display.height() / cell_height);
+
+        let (_, height) = terminal::size()?;
+
+        if rows as u16 != self.rows {
+            self.rows = rows as u16;
+
+            queue!(self.out, ResetColor, Clear(ClearType::All))?;
+
+            // Keep the lines under the picture scrolling on their own, if there are any
+            if height > self.rows + 1 {
+                write!(self.out, "\x1b[{};{}r", self.rows + 1, height)?;
+            }
+        }
+
+        queue!(self.out, MoveTo(0, 0))?;
+
+        let pixel = |x: usize, y: usize| display.pixels()[y * display.width() + x];
+
+        let mut colours = None;
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let (x, y) = (column * cell_width, row * cell_height);
+
+                let (glyph, foreground, background) = match self.glyphs {
+                    // The top pixel is the glyph, the bottom one the background behind it
+                    Glyphs::HalfBlock => ('▀', pixel(x, y), pixel(x, y + 1)),
+                    Glyphs::Braille => {
+                        let mut dots = 0;
+
+                        let mut lit = 0;
+
+                        for (i, &bit) in BRAILLE_DOTS.iter().enumerate() {
+                            let pixel = pixel(x + i % 2, y + i / 2);
+
+                            if pixel != 0 {
+                                dots |= bit;
+                            }
+
+                            // A cell has one colour, so prefer the most planes
+                            lit = lit.max(pixel);
+                        }
+
+                        let glyph = std::char::from_u32(0x2800 + dots).unwrap_or(' ');
+
+                        (glyph, lit, 0)
+                    }
+                };
+
+                // Only change colours when they differ from the last cell's
+                if colours != Some((foreground, background)) {
+                    queue!(
+                        self.out,
+                        SetForegroundColor(colour(palette.colour(foreground))),
+                        SetBackgroundColor(colour(palette.colour(background))),
+                    )?;
+
+                    colours = Some((foreground, background));
+                }
+
+                queue!(self.out, Print(glyph))?;
+            }
+
+            queue!(self.out, ResetColor, MoveToNextLine(1))?;
+
+            colours = None;
+        }
+
+        // Leave the cursor at the bottom, for messages to be printed from
+        queue!(self.out, MoveTo(0, height.saturating_sub(1)))?;
+
+        self.out.flush()
+    }
+}
+
+impl Frontend for Terminal {
+    /// False once Ctrl+C has been pressed or the terminal has gone.
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn is_key_down(&self, key: Key) -> bool {
+        self.held.iter().any(|(held, _)| *held == key)
+    }
+
+    fn is_key_pressed(&self, key: Key) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    fn present(&mut self, display: &Display, palette: &Palette) -> io::Result<()> {
+        self.render(display, palette)
+    }
+
+    fn play(&mut self, cpu: &Cpu) {
+        if let Some(beeper) = &mut self.beeper {
+            beeper.frame(cpu);
+        }
+    }
+
+    fn poll(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        }
+
+        // Don't rush to catch up after a slow frame
+        self.next_frame = self.next_frame.max(now) + Duration::from_secs(1) / FRAME_RATE;
+
+        self.pressed.clear();
+
+        self.read_input()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // Put the terminal back as it was, whether or not each step works
+        if self.releases {
+            let _ = queue!(self.out, PopKeyboardEnhancementFlags);
+        }
+
+        let _ = write!(self.out, "\x1b[r");
+
+        let _ = queue!(self.out, ResetColor, Show, LeaveAlternateScreen);
+
+        let _ = self.out.flush();
+
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// The bit of each dot in a braille character, left to right then top to bottom
+static BRAILLE_DOTS: [u32; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+
+fn colour(rgb: u32) -> Color {
+    Color::Rgb {
+        r: (rgb >> 16) as u8,
+        g: (rgb >> 8) as u8,
+        b: rgb as u8,
+    }
+}
+
+// The key a terminal key code stands for, so keymaps work the same as in a window
+fn host_key(code: KeyCode) -> Option<Key> {
+    let key = match code {
+        KeyCode::Char(c) if c.is_ascii_alphanumeric() => return Key::from_name(&c.to_string()),
+        KeyCode::F(n) => return Key::from_name(&format!("F{}", n)),
+        KeyCode::Char(' ') => Key::Space,
+        KeyCode::Char('\'') => Key::Apostrophe,
+        KeyCode::Char('`') => Key::Backquote,
+        KeyCode::Char('\\') => Key::Backslash,
+        KeyCode::Char(',') => Key::Comma,
+        KeyCode::Char('=') => Key::Equal,
+        KeyCode::Char('[') => Key::LeftBracket,
+        KeyCode::Char('-') => Key::Minus,
+        KeyCode::Char('.') => Key::Period,
+        KeyCode::Char(']') => Key::RightBracket,
+        KeyCode::Char(';') => Key::Semicolon,
+        KeyCode::Char('/') => Key::Slash,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::End => Key::End,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Esc => Key::Escape,
+        KeyCode::Home => Key::Home,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::Tab => Key::Tab,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+// Raw mode also stops newlines returning the cursor to the start of the line,
+// which would stagger the messages printed under the picture
+#[cfg(unix)]
+fn restore_newlines() {
+    // SAFETY: termios is plain data which tcgetattr fills in before it's used
+    unsafe {
+        let mut termios = std::mem::zeroed();
+
+        if libc::tcgetattr(libc::STDOUT_FILENO, &mut termios) == 0 {
+            termios.c_oflag |= libc::OPOST | libc::ONLCR;
+
+            libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &termios);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn restore_newlines() {}
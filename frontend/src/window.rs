@@ -0,0 +1,214 @@
+use crate::{
+    config::Config,
+    frontend::Frontend,
+    keymap::Key,
+    speaker::{self, Speaker},
+};
+use minifb::{KeyRepeat, WindowOptions};
+use mushypeas::{Beeper, Cpu, Display, Palette, FRAME_RATE, SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::{io, time::Duration};
+
+const WINDOW_WIDTH: usize = SCREEN_WIDTH * 10;
+
+const WINDOW_HEIGHT: usize = SCREEN_HEIGHT * 10;
+
+/// A desktop window the display is drawn to and the keyboard is read from.
+pub struct Window {
+    window: minifb::Window,
+    buffer: Vec<u32>,
+    buffer_size: (usize, usize),
+    // Whether the buffer holds a picture the window hasn't shown yet
+    presented: bool,
+    beeper: Option<Beeper<Speaker>>,
+}
+
+impl Window {
+    pub fn open(config: &Config) -> io::Result<Self> {
+        let mut window = minifb::Window::new(
+            "Mushypeas",
+            WINDOW_WIDTH,
+            WINDOW_HEIGHT,
+            WindowOptions::default(),
+        )
+        .map_err(|e| {
+            // Usually there's no display server, such as over SSH
+            io::Error::other(format!(
+                "could not create window ({}), try --frontend tui",
+                e
+            ))
+        })?;
+
+        // Each window update paces one emulated frame
+        window.limit_update_rate(Some(Duration::from_secs(1) / FRAME_RATE));
+
+        Ok(Window {
+            window,
+            buffer: vec![0x0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            buffer_size: (SCREEN_WIDTH, SCREEN_HEIGHT),
+            presented: false,
+            beeper: speaker::open_beeper(config),
+        })
+    }
+}
+
+impl Frontend for Window {
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    fn is_key_down(&self, key: Key) -> bool {
+        self.window.is_key_down(window_key(key))
+    }
+
+    fn is_key_pressed(&self, key: Key) -> bool {
+        self.window.is_key_pressed(window_key(key), KeyRepeat::No)
+    }
+
+    fn present(&mut self, display: &Display, palette: &Palette) -> io::Result<()> {
+        // minifb stretches the buffer to the window, so both resolutions fill it
+        self.buffer.resize(display.width() * display.height(), 0);
+
+        for (pixel, colour) in self.buffer.iter_mut().zip(display.pixels()) {
+            *pixel = palette.colour(*colour);
+        }
+
+        self.buffer_size = (display.width(), display.height());
+
+        self.presented = true;
+
+        Ok(())
+    }
+
+    fn play(&mut self, cpu: &Cpu) {
+        if let Some(beeper) = &mut self.beeper {
+            beeper.frame(cpu);
+        }
+    }
+
+    fn poll(&mut self) -> io::Result<()> {
+        if std::mem::take(&mut self.presented) {
+            let (width, height) = self.buffer_size;
+
+            self.window
+                .update_with_buffer(&self.buffer, width, height)
+                .map_err(|e| io::Error::other(format!("could not update window: {}", e)))?;
+        } else {
+            // Still update the window so input is polled and the frame is paced
+            self.window.update();
+        }
+
+        Ok(())
+    }
+}
+
+// The minifb key of the same name
+fn window_key(key: Key) -> minifb::Key {
+    match key {
+        Key::Key0 => minifb::Key::Key0,
+        Key::Key1 => minifb::Key::Key1,
+        Key::Key2 => minifb::Key::Key2,
+        Key::Key3 => minifb::Key::Key3,
+        Key::Key4 => minifb::Key::Key4,
+        Key::Key5 => minifb::Key::Key5,
+        Key::Key6 => minifb::Key::Key6,
+        Key::Key7 => minifb::Key::Key7,
+        Key::Key8 => minifb::Key::Key8,
+        Key::Key9 => minifb::Key::Key9,
+        Key::A => minifb::Key::A,
+        Key::B => minifb::Key::B,
+        Key::C => minifb::Key::C,
+        Key::D => minifb::Key::D,
+        Key::E => minifb::Key::E,
+        Key::F => minifb::Key::F,
+        Key::G => minifb::Key::G,
+        Key::H => minifb::Key::H,
+        Key::I => minifb::Key::I,
+        Key::J => minifb::Key::J,
+        Key::K => minifb::Key::K,
+        Key::L => minifb::Key::L,
+        Key::M => minifb::Key::M,
+        Key::N => minifb::Key::N,
+        Key::O => minifb::Key::O,
+        Key::P => minifb::Key::P,
+        Key::Q => minifb::Key::Q,
+        Key::R => minifb::Key::R,
+        Key::S => minifb::Key::S,
+        Key::T => minifb::Key::T,
+        Key::U => minifb::Key::U,
+        Key::V => minifb::Key::V,
+        Key::W => minifb::Key::W,
+        Key::X => minifb::Key::X,
+        Key::Y => minifb::Key::Y,
+        Key::Z => minifb::Key::Z,
+        Key::F1 => minifb::Key::F1,
+        Key::F2 => minifb::Key::F2,
+        Key::F3 => minifb::Key::F3,
+        Key::F4 => minifb::Key::F4,
+        Key::F5 => minifb::Key::F5,
+        Key::F6 => minifb::Key::F6,
+        Key::F7 => minifb::Key::F7,
+        Key::F8 => minifb::Key::F8,
+        Key::F9 => minifb::Key::F9,
+        Key::F10 => minifb::Key::F10,
+        Key::F11 => minifb::Key::F11,
+        Key::F12 => minifb::Key::F12,
+        Key::F13 => minifb::Key::F13,
+        Key::F14 => minifb::Key::F14,
+        Key::F15 => minifb::Key::F15,
+        Key::Down => minifb::Key::Down,
+        Key::Left => minifb::Key::Left,
+        Key::Right => minifb::Key::Right,
+        Key::Up => minifb::Key::Up,
+        Key::Apostrophe => minifb::Key::Apostrophe,
+        Key::Backquote => minifb::Key::Backquote,
+        Key::Backslash => minifb::Key::Backslash,
+        Key::Comma => minifb::Key::Comma,
+        Key::Equal => minifb::Key::Equal,
+        Key::LeftBracket => minifb::Key::LeftBracket,
+        Key::Minus => minifb::Key::Minus,
+        Key::Period => minifb::Key::Period,
+        Key::RightBracket => minifb::Key::RightBracket,
+        Key::Semicolon => minifb::Key::Semicolon,
+        Key::Slash => minifb::Key::Slash,
+        Key::Backspace => minifb::Key::Backspace,
+        Key::Delete => minifb::Key::Delete,
+        Key::End => minifb::Key::End,
+        Key::Enter => minifb::Key::Enter,
+        Key::Escape => minifb::Key::Escape,
+        Key::Home => minifb::Key::Home,
+        Key::Insert => minifb::Key::Insert,
+        Key::Menu => minifb::Key::Menu,
+        Key::PageDown => minifb::Key::PageDown,
+        Key::PageUp => minifb::Key::PageUp,
+        Key::Pause => minifb::Key::Pause,
+        Key::Space => minifb::Key::Space,
+        Key::Tab => minifb::Key::Tab,
+        Key::NumLock => minifb::Key::NumLock,
+        Key::CapsLock => minifb::Key::CapsLock,
+        Key::ScrollLock => minifb::Key::ScrollLock,
+        Key::LeftShift => minifb::Key::LeftShift,
+        Key::RightShift => minifb::Key::RightShift,
+        Key::LeftCtrl => minifb::Key::LeftCtrl,
+        Key::RightCtrl => minifb::Key::RightCtrl,
+        Key::LeftAlt => minifb::Key::LeftAlt,
+        Key::RightAlt => minifb::Key::RightAlt,
+        Key::LeftSuper => minifb::Key::LeftSuper,
+        Key::RightSuper => minifb::Key::RightSuper,
+        Key::NumPad0 => minifb::Key::NumPad0,
+        Key::NumPad1 => minifb::Key::NumPad1,
+        Key::NumPad2 => minifb::Key::NumPad2,
+        Key::NumPad3 => minifb::Key::NumPad3,
+        Key::NumPad4 => minifb::Key::NumPad4,
+        Key::NumPad5 => minifb::Key::NumPad5,
+        Key::NumPad6 => minifb::Key::NumPad6,
+        Key::NumPad7 => minifb::Key::NumPad7,
+        Key::NumPad8 => minifb::Key::NumPad8,
+        Key::NumPad9 => minifb::Key::NumPad9,
+        Key::NumPadDot => minifb::Key::NumPadDot,
+        Key::NumPadSlash => minifb::Key::NumPadSlash,
+        Key::NumPadAsterisk => minifb::Key::NumPadAsterisk,
+        Key::NumPadMinus => minifb::Key::NumPadMinus,
+        Key::NumPadPlus => minifb::Key::NumPadPlus,
+        Key::NumPadEnter => minifb::Key::NumPadEnter,
+    }
+}
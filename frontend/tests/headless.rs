@@ -0,0 +1,97 @@
+//! Drives the emulator's run loop on the headless frontend, checking that
+//! scripted keys reach the program through the keymap and hotkeys, and that
+//! runs stop where they should.
+
+use clap::Parser;
+use mushypeas_frontend::{
+    config::Config,
+    emulator::Emulator,
+    headless::Headless,
+    keymap::{Key, Keymap},
+};
+
+// Runs `source` with any extra command line `args`
+fn run(
+    args: &[&str],
+    source: &str,
+    frontend: Headless,
+    max_cycles: Option<u64>,
+) -> Emulator<Headless> {
+    let args = ["mushypeas"].iter().chain(args).chain(&["run", "test.ch8"]);
+
+    let config = Config::try_parse_from(args).unwrap();
+
+    let mut emulator = Emulator::new(config, frontend, Keymap::default(), None).unwrap();
+
+    if let Some(cycles) = max_cycles {
+        emulator.set_max_cycles(cycles);
+    }
+
+    let rom = mushypeas::assemble(source).unwrap_or_else(|e| panic!("{}\n{}", e, source));
+
+    emulator.run(&rom).unwrap();
+
+    emulator
+}
+
+#[test]
+fn keys_are_pressed_through_the_keymap() {
+    let mut frontend = Headless::new();
+
+    // W is CHIP-8 key 5 in the default layout
+    frontend.press(5, Key::W);
+
+    frontend.release(6, Key::W);
+
+    let emulator = run(&[], "v0 := key\nexit", frontend, None);
+
+    assert!(emulator.machine().cpu().halted());
+
+    assert_eq!(emulator.machine().cpu().registers()[0], 5);
+
+    // The wait finishes on the frame the key is released
+    assert_eq!(emulator.machine().frames(), 6);
+}
+
+#[test]
+fn the_quit_hotkey_stops_the_run() {
+    let mut frontend = Headless::new();
+
+    frontend.press(3, Key::Escape);
+
+    let emulator = run(&[], ": loop jump loop", frontend, None);
+
+    assert_eq!(emulator.machine().frames(), 3);
+}
+
+#[test]
+fn runs_stop_after_the_cycles_or_a_fault() {
+    let emulator = run(&[], ": loop jump loop", Headless::new(), Some(25));
+
+    assert_eq!(emulator.cycles(), 25);
+
+    assert_eq!(emulator.machine().frames(), 2);
+
+    assert!(emulator.fault().is_none());
+
+    // The faulting instruction still counts as a cycle
+    let emulator = run(&[], "v0 := 1\n;", Headless::new(), Some(100));
+
+    assert_eq!(emulator.cycles(), 2);
+
+    assert!(emulator.fault().is_some());
+}
+
+#[test]
+fn ignored_faults_dont_slow_frames_down() {
+    let mut frontend = Headless::new();
+
+    frontend.press(3, Key::Escape);
+
+    let source = ": loop 0xFF 0xFF jump loop";
+
+    let emulator = run(&["--on-fault", "ignore"], source, frontend, None);
+
+    // Each poll still runs a whole frame, however many faults it ignores
+    assert_eq!(emulator.machine().frames(), 3);
+}
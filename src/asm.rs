@@ -0,0 +1,797 @@
+use crate::{
+    cpu::{INSTR_START, REGS},
+    instr::Instr,
+    EmulatorError, Result,
+};
+use std::collections::{HashMap, VecDeque};
+
+// Guards against macros which expand into themselves forever
+const MAX_EXPANSIONS: usize = 10_000;
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+// Where an address that isn't known yet has to be written once it is
+#[derive(Copy, Clone, Debug)]
+enum Patch {
+    // The low 12 bits of an opcode
+    Nnn,
+    // The 16 bit operand of `i := long`
+    Long,
+}
+
+#[derive(Clone, Debug)]
+struct Fixup {
+    pos: usize,
+    patch: Patch,
+    label: String,
+    line: usize,
+}
+
+// An open `if ... begin` or `loop`, waiting for its `else`, `end` or `again`
+#[derive(Copy, Clone, Debug)]
+enum Block {
+    If { jump: usize },
+    Else { jump: usize },
+    Loop { start: usize },
+}
+
+#[derive(Clone, Debug)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+// An address operand, which may refer to a label that comes later on
+enum Value {
+    Known(usize),
+    Forward(String),
+}
+
+/// Assembles Octo source into a ROM to be loaded at 0x200.
+///
+/// Supports every instruction along with labels, `:const`, `:alias`, `:macro`,
+/// `:byte`, `:org`, `:call`, `if ... then`, `if ... begin ... else ... end` and
+/// `loop ... again`. Unlike Octo, code is assembled from 0x200 in the order it's
+/// written, so there's no implicit jump to `main`. This is enough to read back in
+/// the output of [`disassemble`](crate::disassemble).
+pub fn assemble(source: &str) -> Result<Vec<u8>> {
+    let mut assembler = Assembler::new(tokenize(source));
+
+    assembler.run()?;
+
+    Ok(assembler.rom)
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(i, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+
+            code.split_whitespace().map(move |text| Token {
+                text: text.to_string(),
+                line: i + 1,
+            })
+        })
+        .collect()
+}
+
+fn error(line: usize, message: impl Into<String>) -> EmulatorError {
+    EmulatorError::AsmError {
+        line,
+        message: message.into(),
+    }
+}
+
+fn parse_number(s: &str) -> Option<i64> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(s: &str) -> Option<usize> {
+    let digit = s.strip_prefix('v').or(s.strip_prefix('V'))?;
+
+    match usize::from_str_radix(digit, 16) {
+        Ok(reg) if digit.len() == 1 && reg < REGS => Some(reg),
+        _ => None,
+    }
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    rom: Vec<u8>,
+    pos: usize,
+    line: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, usize)>,
+    expansions: usize,
+}
+
+impl Assembler {
+    fn new(tokens: VecDeque<Token>) -> Self {
+        Assembler {
+            tokens,
+            rom: Vec::new(),
+            pos: 0,
+            line: 1,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            expansions: 0,
+        }
+    }
+
+    fn run(&mut self) -> Result {
+        while let Some(token) = self.tokens.pop_front() {
+            self.line = token.line;
+
+            self.statement(&token.text)?;
+        }
+
+        if let Some(&(block, line)) = self.blocks.last() {
+            let message = match block {
+                Block::If { .. } | Block::Else { .. } => "'begin' is missing its 'end'",
+                Block::Loop { .. } => "'loop' is missing its 'again'",
+            };
+
+            return Err(error(line, message));
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let addr = *self
+                .labels
+                .get(&fixup.label)
+                .ok_or_else(|| error(fixup.line, format!("undefined name '{}'", fixup.label)))?;
+
+            self.line = fixup.line;
+
+            self.patch(fixup.pos, fixup.patch, addr)?;
+        }
+
+        Ok(())
+    }
+
+    fn statement(&mut self, text: &str) -> Result {
+        match text {
+            ":" => {
+                let name = self.name()?;
+
+                if self.labels.insert(name.clone(), self.here()).is_some() {
+                    return Err(self.error(format!("label '{}' is already defined", name)));
+                }
+            }
+            ":const" => {
+                let name = self.name()?;
+
+                let value = self.number()?;
+
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+
+                let reg = self.register()?;
+
+                self.aliases.insert(name, reg);
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let byte = self.byte()?;
+
+                self.emit(&[byte]);
+            }
+            ":org" => {
+                let addr = self.number()?;
+
+                if addr < INSTR_START as i64 {
+                    return Err(self.error(format!(":org {:#05X} is before 0x200", addr)));
+                }
+
+                self.pos = addr as usize - INSTR_START;
+            }
+            ":call" => self.call()?,
+            "clear" => self.instr(Instr::Clear),
+            "return" | ";" => self.instr(Instr::Return),
+            "exit" => self.instr(Instr::Exit),
+            "lores" => self.instr(Instr::LoRes),
+            "hires" => self.instr(Instr::HiRes),
+            "scroll-down" => {
+                let rows = self.nibble()?;
+
+                self.instr(Instr::ScrollDown { rows });
+            }
+            "scroll-up" => {
+                let rows = self.nibble()?;
+
+                self.instr(Instr::ScrollUp { rows });
+            }
+            "scroll-left" => self.instr(Instr::ScrollLeft),
+            "scroll-right" => self.instr(Instr::ScrollRight),
+            "audio" => self.instr(Instr::LoadAudioPattern),
+            "jump" => self.addr_instr(|addr| Instr::Jump { addr })?,
+            "jump0" => self.addr_instr(|addr| Instr::JumpTo { addr })?,
+            "bcd" => {
+                let reg = self.register()?;
+
+                self.instr(Instr::StoreBCDAtIndex { reg });
+            }
+            "save" | "load" => {
+                let save = text == "save";
+
+                let first = self.register()?;
+
+                if self.peek() == Some("-") {
+                    self.next()?;
+
+                    let last = self.register()?;
+
+                    self.instr(if save {
+                        Instr::StoreRegisterRange { first, last }
+                    } else {
+                        Instr::ReadRegisterRange { first, last }
+                    });
+                } else if save {
+                    self.instr(Instr::StoreRegistersAtIndex { start_addr: first });
+                } else {
+                    self.instr(Instr::ReadRegistersAtIndex { start_addr: first });
+                }
+            }
+            "saveflags" => {
+                let last_reg = self.register()?;
+
+                self.instr(Instr::StoreRegistersInFlags { last_reg });
+            }
+            "loadflags" => {
+                let last_reg = self.register()?;
+
+                self.instr(Instr::ReadRegistersFromFlags { last_reg });
+            }
+            "sprite" => {
+                let x = self.register()?;
+
+                let y = self.register()?;
+
+                let size = self.nibble()?;
+
+                self.instr(match size {
+                    0 => Instr::DrawLargeSprite { x, y },
+                    _ => Instr::DrawSprite { x, y, size },
+                });
+            }
+            "plane" => {
+                let planes = self.nibble()?;
+
+                self.instr(Instr::SelectPlanes { planes });
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+
+                let reg = self.register()?;
+
+                self.instr(match text {
+                    "delay" => Instr::SetDelayTimerRegister { reg },
+                    "buzzer" => Instr::SetSoundTimerRegister { reg },
+                    _ => Instr::SetPitchRegister { reg },
+                });
+            }
+            "i" => self.index()?,
+            "if" => self.conditional()?,
+            "else" => match self.blocks.pop() {
+                Some((Block::If { jump }, line)) => {
+                    let here = self.here();
+
+                    let skip = self.pos;
+
+                    self.instr(Instr::Jump { addr: 0 });
+
+                    self.patch(jump, Patch::Nnn, here + 2)?;
+
+                    self.blocks.push((Block::Else { jump: skip }, line));
+                }
+                _ => return Err(self.error("'else' without a matching 'begin'")),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::If { jump }, _)) | Some((Block::Else { jump }, _)) => {
+                    self.patch(jump, Patch::Nnn, self.here())?;
+                }
+                _ => return Err(self.error("'end' without a matching 'begin'")),
+            },
+            "loop" => {
+                let start = self.here();
+
+                self.blocks.push((Block::Loop { start }, self.line));
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start }, _)) => self.instr(Instr::Jump { addr: start }),
+                _ => return Err(self.error("'again' without a matching 'loop'")),
+            },
+            _ => {
+                if let Some(reg) = self.lookup_register(text) {
+                    self.register_statement(reg)?;
+                } else if self.macros.contains_key(text) {
+                    self.expand_macro(text)?;
+                } else if let Some(value) = parse_number(text) {
+                    let byte = self.to_byte(value)?;
+
+                    self.emit(&[byte]);
+                } else if is_name(text) {
+                    // A bare label is a call, as in Octo
+                    self.tokens.push_front(Token {
+                        text: text.to_string(),
+                        line: self.line,
+                    });
+
+                    self.call()?;
+                } else {
+                    return Err(self.error(format!("unexpected '{}'", text)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Everything starting with a register, like `v0 := 5` or `v1 <<= v2`
+    fn register_statement(&mut self, reg: usize) -> Result {
+        let op = self.next()?;
+
+        if op == ":=" {
+            match self.peek() {
+                Some("random") => {
+                    self.next()?;
+
+                    let lit = self.byte()?;
+
+                    self.instr(Instr::RandBitwiseAnd { reg, lit });
+                }
+                Some("key") => {
+                    self.next()?;
+
+                    self.instr(Instr::KeyPressWait { reg });
+                }
+                Some("delay") => {
+                    self.next()?;
+
+                    self.instr(Instr::SetDelayTimerValue { reg });
+                }
+                _ => match self.operand()? {
+                    Ok(right) => self.instr(Instr::RegisterSetRegister { left: reg, right }),
+                    Err(lit) => self.instr(Instr::RegisterSetLiteral { reg, lit }),
+                },
+            }
+
+            return Ok(());
+        }
+
+        if op == "+=" {
+            match self.operand()? {
+                Ok(right) => self.instr(Instr::RegisterSetRegisterAdd { left: reg, right }),
+                Err(lit) => self.instr(Instr::RegisterAddAssign { reg, lit }),
+            }
+
+            return Ok(());
+        }
+
+        let right = self.register()?;
+
+        let left = reg;
+
+        self.instr(match op.as_str() {
+            "|=" => Instr::RegisterSetRegisterBitwiseOr { left, right },
+            "&=" => Instr::RegisterSetRegisterBitwiseAnd { left, right },
+            "^=" => Instr::RegisterSetRegisterBitwiseXor { left, right },
+            "-=" => Instr::RegisterSetRegisterSub { left, right },
+            "=-" => Instr::RegisterSetRegisterSubn { left, right },
+            ">>=" => Instr::RegisterSetRegisterShr { left, right },
+            "<<=" => Instr::RegisterSetRegisterShl { left, right },
+            _ => return Err(self.error(format!("unknown operator '{}'", op))),
+        });
+
+        Ok(())
+    }
+
+    fn index(&mut self) -> Result {
+        let op = self.next()?;
+
+        if op == "+=" {
+            let reg = self.register()?;
+
+            self.instr(Instr::IndexAddAssignRegister { reg });
+
+            return Ok(());
+        }
+
+        if op != ":=" {
+            return Err(self.error(format!("expected ':=' or '+=' after 'i', found '{}'", op)));
+        }
+
+        match self.peek() {
+            Some("hex") => {
+                self.next()?;
+
+                let reg = self.register()?;
+
+                self.instr(Instr::SetIndexToDigitSprite { reg });
+            }
+            Some("bighex") => {
+                self.next()?;
+
+                let reg = self.register()?;
+
+                self.instr(Instr::SetIndexToBigDigitSprite { reg });
+            }
+            Some("long") => {
+                self.next()?;
+
+                let pos = self.pos;
+
+                match self.value()? {
+                    Value::Known(value) if value <= 0xFFFF => {
+                        self.instr(Instr::SetIndexLong { value })
+                    }
+                    Value::Known(value) => {
+                        return Err(self.error(format!("address {:#X} is over 16 bits", value)))
+                    }
+                    Value::Forward(label) => {
+                        self.instr(Instr::SetIndexLong { value: 0 });
+
+                        self.fixup(pos, Patch::Long, label);
+                    }
+                }
+            }
+            _ => self.addr_instr(|value| Instr::SetIndex { value })?,
+        }
+
+        Ok(())
+    }
+
+    // `if <condition> then` skips the next instruction unless the condition holds,
+    // while `if <condition> begin` jumps past the block (or to its `else`)
+    fn conditional(&mut self) -> Result {
+        let reg = self.register()?;
+
+        let op = self.next()?;
+
+        // Each condition pairs the instruction for `then` with its opposite for `begin`
+        let (then, begin) = match op.as_str() {
+            "key" => (
+                Instr::SkipNextKeyNotPressed { reg },
+                Instr::SkipNextKeyPressed { reg },
+            ),
+            "-key" => (
+                Instr::SkipNextKeyPressed { reg },
+                Instr::SkipNextKeyNotPressed { reg },
+            ),
+            "==" | "!=" => {
+                let (equal, not_equal) = match self.operand()? {
+                    Ok(right) => (
+                        Instr::SkipNextNotEqualRegister { left: reg, right },
+                        Instr::SkipNextEqualRegister { left: reg, right },
+                    ),
+                    Err(lit) => (
+                        Instr::SkipNextNotEqualLiteral { reg, lit },
+                        Instr::SkipNextEqualLiteral { reg, lit },
+                    ),
+                };
+
+                if op == "==" {
+                    (equal, not_equal)
+                } else {
+                    (not_equal, equal)
+                }
+            }
+            _ => return Err(self.error(format!("unsupported comparison '{}'", op))),
+        };
+
+        match self.next()?.as_str() {
+            "then" => self.instr(then),
+            "begin" => {
+                self.instr(begin);
+
+                let jump = self.pos;
+
+                self.instr(Instr::Jump { addr: 0 });
+
+                self.blocks.push((Block::If { jump }, self.line));
+            }
+            other => {
+                return Err(self.error(format!("expected 'then' or 'begin', found '{}'", other)))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn call(&mut self) -> Result {
+        self.addr_instr(|addr| Instr::Call { addr })
+    }
+
+    fn define_macro(&mut self) -> Result {
+        let name = self.name()?;
+
+        let mut params = Vec::new();
+
+        loop {
+            match self.next()?.as_str() {
+                "{" => break,
+                param => params.push(param.to_string()),
+            }
+        }
+
+        let mut body = Vec::new();
+
+        let mut depth = 1;
+
+        loop {
+            let token = self
+                .tokens
+                .pop_front()
+                .ok_or_else(|| self.error(format!("macro '{}' is missing its '}}'", name)))?;
+
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+
+            if depth == 0 {
+                break;
+            }
+
+            body.push(token);
+        }
+
+        self.macros.insert(name, Macro { params, body });
+
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result {
+        self.expansions += 1;
+
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(format!("macro '{}' expands forever", name)));
+        }
+
+        let Macro { params, body } = self.macros[name].clone();
+
+        let mut args = HashMap::new();
+
+        let arity = match params.len() {
+            1 => "1 argument".to_string(),
+            count => format!("{} arguments", count),
+        };
+
+        for param in params {
+            let arg = self
+                .next()
+                .map_err(|_| self.error(format!("macro '{}' takes {}", name, arity)))?;
+
+            args.insert(param, arg);
+        }
+
+        // Expanded tokens take the invocation's line so errors point at it
+        for token in body.into_iter().rev() {
+            let text = args.get(&token.text).cloned().unwrap_or(token.text);
+
+            self.tokens.push_front(Token {
+                text,
+                line: self.line,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Emits an instruction taking an address, leaving a fixup if it's a forward reference
+    fn addr_instr(&mut self, instr: impl Fn(usize) -> Instr) -> Result {
+        let pos = self.pos;
+
+        match self.value()? {
+            Value::Known(addr) if addr <= 0xFFF => self.instr(instr(addr)),
+            Value::Known(addr) => {
+                return Err(self.error(format!("address {:#X} is over 12 bits", addr)))
+            }
+            Value::Forward(label) => {
+                self.instr(instr(0));
+
+                self.fixup(pos, Patch::Nnn, label);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fixup(&mut self, pos: usize, patch: Patch, label: String) {
+        self.fixups.push(Fixup {
+            pos,
+            patch,
+            label,
+            line: self.line,
+        });
+    }
+
+    fn patch(&mut self, pos: usize, patch: Patch, addr: usize) -> Result {
+        match patch {
+            Patch::Nnn => {
+                if addr > 0xFFF {
+                    return Err(self.error(format!("address {:#X} is over 12 bits", addr)));
+                }
+
+                self.rom[pos] = (self.rom[pos] & 0xF0) | (addr >> 8) as u8;
+
+                self.rom[pos + 1] = addr as u8;
+            }
+            Patch::Long => {
+                self.rom[pos + 2] = (addr >> 8) as u8;
+
+                self.rom[pos + 3] = addr as u8;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn instr(&mut self, instr: Instr) {
+        self.emit(&instr.encode());
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        let end = self.pos + bytes.len();
+
+        if self.rom.len() < end {
+            self.rom.resize(end, 0);
+        }
+
+        self.rom[self.pos..end].copy_from_slice(bytes);
+
+        self.pos = end;
+    }
+
+    fn here(&self) -> usize {
+        INSTR_START + self.pos
+    }
+
+    fn next(&mut self) -> Result<String> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+
+                Ok(token.text)
+            }
+            None => Err(self.error("unexpected end of file")),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result {
+        let found = self.next()?;
+
+        if found == expected {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}', found '{}'", expected, found)))
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let name = self.next()?;
+
+        if is_name(&name) && self.lookup_register(&name).is_none() {
+            Ok(name)
+        } else {
+            Err(self.error(format!("'{}' can't be used as a name", name)))
+        }
+    }
+
+    fn lookup_register(&self, text: &str) -> Option<usize> {
+        parse_register(text).or_else(|| self.aliases.get(text).copied())
+    }
+
+    fn register(&mut self) -> Result<usize> {
+        let text = self.next()?;
+
+        self.lookup_register(&text)
+            .ok_or_else(|| self.error(format!("expected a register, found '{}'", text)))
+    }
+
+    // Either a register or a byte, for instructions which come in both forms
+    fn operand(&mut self) -> Result<std::result::Result<usize, u8>> {
+        match self.peek().and_then(|text| self.lookup_register(text)) {
+            Some(reg) => {
+                self.next()?;
+
+                Ok(Ok(reg))
+            }
+            None => self.byte().map(Err),
+        }
+    }
+
+    fn number(&mut self) -> Result<i64> {
+        let text = self.next()?;
+
+        match self.constant(&text) {
+            Some(value) => Ok(value),
+            None => Err(self.error(format!("expected a number, found '{}'", text))),
+        }
+    }
+
+    fn constant(&self, text: &str) -> Option<i64> {
+        parse_number(text)
+            .or_else(|| self.constants.get(text).copied())
+            .or_else(|| self.labels.get(text).map(|&addr| addr as i64))
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let text = self.next()?;
+
+        match self.constant(&text) {
+            Some(value) if value < 0 => Err(self.error(format!("address {} is negative", value))),
+            Some(value) => Ok(Value::Known(value as usize)),
+            None if is_name(&text) => Ok(Value::Forward(text)),
+            None => Err(self.error(format!("expected an address, found '{}'", text))),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let value = self.number()?;
+
+        self.to_byte(value)
+    }
+
+    fn to_byte(&self, value: i64) -> Result<u8> {
+        if (-128..=255).contains(&value) {
+            Ok(value as u8)
+        } else {
+            Err(self.error(format!("{} doesn't fit in a byte", value)))
+        }
+    }
+
+    fn nibble(&mut self) -> Result<u8> {
+        let value = self.number()?;
+
+        if (0..=15).contains(&value) {
+            Ok(value as u8)
+        } else {
+            Err(self.error(format!("{} doesn't fit in a nibble", value)))
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> EmulatorError {
+        error(self.line, message)
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
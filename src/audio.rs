@@ -0,0 +1,135 @@
+use crate::{
+    cpu::{Cpu, DEFAULT_PITCH},
+    machine::FRAME_RATE,
+};
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+pub const DEFAULT_FREQUENCY: f32 = 440.0;
+
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+// XO-CHIP plays its pattern at 4000 bits per second when the pitch register is 64
+const PATTERN_BASE_RATE: f32 = 4000.0;
+
+/// Somewhere to send the mono samples produced while the sound timer is active.
+pub trait AudioSink {
+    fn queue(&mut self, samples: &[f32]);
+}
+
+/// A sink which keeps every sample, for headless runs and tests.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySink {
+    samples: Vec<f32>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        MemorySink::default()
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+}
+
+impl AudioSink for MemorySink {
+    fn queue(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+    }
+}
+
+/// Turns the sound timer into samples, one frame's worth at a time.
+///
+/// Plays a square wave at `frequency`, or the XO-CHIP audio pattern once a program has loaded one.
+#[derive(Clone, Debug)]
+pub struct Beeper<S: AudioSink> {
+    sink: S,
+    sample_rate: u32,
+    frequency: f32,
+    volume: f32,
+    phase: f32,
+    // Fractional samples carried over so each second gets exactly `sample_rate` samples
+    remainder: f32,
+    buffer: Vec<f32>,
+}
+
+impl<S: AudioSink> Beeper<S> {
+    pub fn new(sink: S, sample_rate: u32) -> Self {
+        Beeper {
+            sink,
+            sample_rate,
+            frequency: DEFAULT_FREQUENCY,
+            volume: DEFAULT_VOLUME,
+            phase: 0.0,
+            remainder: 0.0,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    /// Produces the samples for the frame that just ran on `cpu`.
+    pub fn frame(&mut self, cpu: &Cpu) {
+        let samples = self.sample_rate as f32 / FRAME_RATE as f32 + self.remainder;
+
+        self.remainder = samples.fract();
+
+        self.buffer.clear();
+
+        if cpu.sound_timer() == 0 {
+            // Restart the waveform so every beep begins the same way
+            self.phase = 0.0;
+
+            self.buffer.resize(samples as usize, 0.0);
+        } else {
+            let (step, pattern) = match cpu.audio_pattern() {
+                Some(pattern) => {
+                    let octaves = (cpu.pitch() as f32 - DEFAULT_PITCH as f32) / 48.0;
+
+                    let rate = PATTERN_BASE_RATE * 2f32.powf(octaves);
+
+                    // One pass over the pattern's 128 bits is a single cycle
+                    (rate / (pattern.len() * 8) as f32, Some(pattern))
+                }
+                None => (self.frequency, None),
+            };
+
+            for _ in 0..samples as usize {
+                let high = match pattern {
+                    Some(pattern) => {
+                        let bit = (self.phase * (pattern.len() * 8) as f32) as usize;
+
+                        (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1
+                    }
+                    None => self.phase < 0.5,
+                };
+
+                self.buffer
+                    .push(if high { self.volume } else { -self.volume });
+
+                self.phase = (self.phase + step / self.sample_rate as f32).fract();
+            }
+        }
+
+        self.sink.queue(&self.buffer);
+    }
+}
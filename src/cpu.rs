@@ -1,130 +1,484 @@
-use crate::{fonts, instr::Instr, opcode::Opcode, config::Config, Result};
+use crate::{
+    display::{Display, NUM_PLANES},
+    fonts,
+    instr::Instr,
+    opcode::Opcode,
+    quirks::{Platform, Quirks},
+    rng::Rng,
+    state::{self, StateReader, StateWriter},
+    EmulatorError, Result,
+};
 use std::convert::TryInto;
 
-pub(crate) const SCREEN_WIDTH: usize = 64;
+pub const NUM_KEYS: usize = 16;
 
-pub(crate) const SCREEN_HEIGHT: usize = 32;
+pub const MAX_INSTRS: usize = MEM_SIZE - INSTR_START;
 
-pub(crate) const NUM_KEYS: usize = 16;
+// XO-CHIP's full 64KiB address space, reachable with F000 NNNN
+pub const MEM_SIZE: usize = 0x10000;
 
-pub(crate) const MAX_INSTRS: usize = MEM_SIZE - INSTR_START;
+pub const REGS: usize = 16;
 
-const MEM_SIZE: usize = 4096;
+pub const STACK_SIZE: usize = 16;
 
-const INSTR_START: usize = 0x200;
+/// The number of SUPER-CHIP RPL user flags that FX75/FX85 can use.
+pub const NUM_FLAGS: usize = 16;
+
+/// The size in bytes of the XO-CHIP audio pattern buffer loaded by F002.
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+
+/// The XO-CHIP pitch register's initial value, which plays the pattern at 4000Hz.
+pub const DEFAULT_PITCH: u8 = 64;
+
+pub(crate) const INSTR_START: usize = 0x200;
 
 const INSTR_SIZE: usize = 2;
 
-const REGS: usize = 16;
+const BIG_FONT_START: usize = 0x50;
 
-const STACK_SIZE: usize = 16;
+const SCROLL_COLUMNS: usize = 4;
 
 #[derive(Clone, Debug)]
-pub(crate) struct Cpu {
-    pub(crate) display: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
-    pub(crate) keys: [bool; NUM_KEYS],
-    pub(crate) should_rerender: bool,
-    config: Config,
+pub struct Cpu {
+    display: Display,
+    keys: [bool; NUM_KEYS],
+    // Keys that went down or up since the frame began, so FX0A can tell a
+    // fresh press from a key that was already held
+    key_presses: [bool; NUM_KEYS],
+    key_releases: [bool; NUM_KEYS],
+    // The key FX0A saw pressed and is waiting to be released
+    key_wait: Option<usize>,
+    should_rerender: bool,
+    // Set by a draw under the display wait quirk, until the machine stalls for it
+    display_wait: bool,
+    quirks: Quirks,
     stack: [usize; STACK_SIZE],
     registers: [u8; REGS],
+    flags: [u8; NUM_FLAGS],
     memory: [u8; MEM_SIZE],
+    // How much of `memory` the platform has, beyond which accesses fault
+    memory_size: usize,
     ticks: u128,
     index: usize,
     pc: usize,
     sp: usize,
     delay_timer: u8,
     sound_timer: u8,
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    rng: Rng,
+    halted: bool,
 }
 
 impl Cpu {
-    pub(crate) fn new(config: Config) -> Self {
+    pub fn new() -> Self {
         Cpu {
-            display: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            display: Display::new(),
             keys: [false; NUM_KEYS],
+            key_presses: [false; NUM_KEYS],
+            key_releases: [false; NUM_KEYS],
+            key_wait: None,
             should_rerender: false,
-            config,
+            display_wait: false,
+            quirks: Quirks::default(),
             stack: [0; STACK_SIZE],
             registers: [0; REGS],
+            flags: [0; NUM_FLAGS],
             memory: [0; MEM_SIZE],
+            memory_size: Platform::default().memory_size(),
             ticks: 0,
             index: 0,
             pc: INSTR_START,
             sp: 0,
             delay_timer: 0,
             sound_timer: 0,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            rng: Rng::default(),
+            halted: false,
         }
     }
 
-    pub(crate) fn load(&mut self, instrs: &[u8]) {
-        self.memory[0..fonts::FONTS.len()].clone_from_slice(fonts::FONTS);
+    /// Loads the fonts, and the program at 0x200, failing if it's too big for
+    /// the memory above it.
+    pub fn load(&mut self, instrs: &[u8]) -> Result {
+        let max = self.memory_size - INSTR_START;
 
-        for (i, instr) in instrs.iter().enumerate() {
-            self.memory[INSTR_START + i] = *instr;
+        if instrs.len() > max {
+            return Err(EmulatorError::RomTooLarge {
+                size: instrs.len(),
+                max,
+            });
         }
 
-        //self.memory[INSTR_START..INSTR_START + instrs.len()].clone_from_slice(instrs as &[usize]);
+        self.memory[0..fonts::FONTS.len()].clone_from_slice(fonts::FONTS);
+
+        self.memory[BIG_FONT_START..BIG_FONT_START + fonts::BIG_FONTS.len()]
+            .clone_from_slice(fonts::BIG_FONTS);
+
+        self.memory[INSTR_START..INSTR_START + instrs.len()].clone_from_slice(instrs);
+
+        Ok(())
     }
 
-    pub(crate) fn run(&mut self) {
-        if self.pc > MEM_SIZE - 1 {
-            return;
+    /// Executes the instruction at the PC.
+    ///
+    /// A fault leaves the CPU as it was before the instruction, with the PC still
+    /// pointing at it, so a frontend can inspect it or [`advance`](Cpu::advance) past it.
+    pub fn step(&mut self) -> Result {
+        if self.halted {
+            return Ok(());
         }
 
+        if self.pc > self.memory_size - INSTR_SIZE {
+            return Err(EmulatorError::PcOutOfBounds { pc: self.pc });
+        }
+
+        let instr = self.decode_instr(self.pc)?;
+
+        self.eval(&instr)?;
+
         self.ticks += 1;
 
-        // Attempt to decrement the timers 60 times per second (at 500Hz)
-        // TODO: Add configurable running rate
+        Ok(())
+    }
+
+    /// Moves the PC past the current instruction without executing it, to carry on after a fault.
+    pub fn advance(&mut self) {
+        self.pc += self
+            .decode_instr(self.pc)
+            .map_or(INSTR_SIZE, |instr| instr.size());
+    }
+
+    /// Decrements the delay and sound timers, which should happen at 60Hz.
+    pub fn tick_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
 
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
+        }
+    }
 
-            if !self.config.mute {
-                // TODO: Add proper beep sound
-                println!("Beep");
-            }
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    /// Replaces the generator behind CXKK, such as to reseed it.
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// The size of the address space, such as from [`Platform::memory_size`].
+    pub fn memory_size(&self) -> usize {
+        self.memory_size
+    }
+
+    /// Limits the address space to `size` bytes, past which the program faults,
+    /// up to XO-CHIP's 64KiB.
+    pub fn set_memory_size(&mut self, size: usize) {
+        self.memory_size = size.clamp(INSTR_START + INSTR_SIZE, MEM_SIZE);
+    }
+
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// Returns whether the display changed since the last call.
+    pub fn take_rerender(&mut self) -> bool {
+        std::mem::take(&mut self.should_rerender)
+    }
+
+    /// Returns whether a sprite was drawn that should stall the CPU until the
+    /// next frame, clearing it.
+    pub(crate) fn take_display_wait(&mut self) -> bool {
+        std::mem::take(&mut self.display_wait)
+    }
+
+    pub fn keys(&self) -> &[bool; NUM_KEYS] {
+        &self.keys
+    }
+
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        if pressed && !self.keys[key] {
+            self.key_presses[key] = true;
+        } else if !pressed && self.keys[key] {
+            self.key_releases[key] = true;
         }
 
-        match Cpu::decode_instr(self.memory[self.pc], self.memory[self.pc + 1]) {
-            Ok(ref instr) => self.eval(instr),
-            Err(e) => println!("{:?}, pc: {}", e, self.pc),
+        self.keys[key] = pressed;
+    }
+
+    /// The keys pressed since the frame began, even if already released again.
+    pub fn key_presses(&self) -> &[bool; NUM_KEYS] {
+        &self.key_presses
+    }
+
+    /// The keys released since the frame began, even if already pressed again.
+    pub fn key_releases(&self) -> &[bool; NUM_KEYS] {
+        &self.key_releases
+    }
+
+    /// Forgets the presses and releases so far, as each frame ends.
+    pub fn clear_key_events(&mut self) {
+        self.key_presses = [false; NUM_KEYS];
+
+        self.key_releases = [false; NUM_KEYS];
+    }
+
+    /// The key FX0A is waiting to be released, if it's seen one pressed.
+    pub fn key_wait(&self) -> Option<usize> {
+        self.key_wait
+    }
+
+    pub fn registers(&self) -> &[u8; REGS] {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn ticks(&self) -> u128 {
+        self.ticks
+    }
+
+    /// The XO-CHIP audio pattern, if the program has loaded one with F002.
+    pub fn audio_pattern(&self) -> Option<&[u8; AUDIO_PATTERN_SIZE]> {
+        self.audio_pattern.as_ref()
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn flags(&self) -> &[u8; NUM_FLAGS] {
+        &self.flags
+    }
+
+    /// Whether the program has stopped itself with 00FD.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        self.display.save_state(writer);
+
+        writer.bytes(&self.memory);
+
+        writer.bytes(&self.registers);
+
+        writer.bytes(&self.flags);
+
+        for addr in self.stack.iter() {
+            writer.usize(*addr);
         }
+
+        writer.usize(self.sp);
+
+        writer.usize(self.pc);
+
+        writer.usize(self.index);
+
+        writer.u8(self.delay_timer);
+
+        writer.u8(self.sound_timer);
+
+        writer.u128(self.ticks);
+
+        writer.bool(self.audio_pattern.is_some());
+
+        writer.bytes(&self.audio_pattern.unwrap_or_default());
+
+        writer.u8(self.pitch);
+
+        self.rng.save_state(writer);
+
+        writer.bool(self.halted);
+
+        writer.bool(self.key_wait.is_some());
+
+        writer.usize(self.key_wait.unwrap_or_default());
     }
 
-    fn decode_instr(left: u8, right: u8) -> Result<Instr> {
-        let instr = (left as u16) << 8 | (right) as u16;
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result {
+        self.display.load_state(reader)?;
+
+        reader.fill(&mut self.memory)?;
+
+        reader.fill(&mut self.registers)?;
 
-        let opcode = Opcode::new(instr);
+        reader.fill(&mut self.flags)?;
 
-        opcode.try_into()
+        for addr in self.stack.iter_mut() {
+            *addr = reader.usize()?;
+        }
+
+        self.sp = reader.usize()?;
+
+        if self.sp > STACK_SIZE {
+            return Err(state::invalid("stack pointer out of range"));
+        }
+
+        self.pc = reader.usize()?;
+
+        self.index = reader.usize()?;
+
+        self.delay_timer = reader.u8()?;
+
+        self.sound_timer = reader.u8()?;
+
+        self.ticks = reader.u128()?;
+
+        let has_pattern = reader.bool()?;
+
+        let mut pattern = [0; AUDIO_PATTERN_SIZE];
+
+        reader.fill(&mut pattern)?;
+
+        self.audio_pattern = if has_pattern { Some(pattern) } else { None };
+
+        self.pitch = reader.u8()?;
+
+        self.rng.load_state(reader)?;
+
+        self.halted = reader.bool()?;
+
+        let waiting = reader.bool()?;
+
+        let key = reader.usize()?;
+
+        if key >= NUM_KEYS {
+            return Err(state::invalid("awaited key out of range"));
+        }
+
+        self.key_wait = if waiting { Some(key) } else { None };
+
+        self.should_rerender = true;
+
+        Ok(())
     }
 
-    fn eval(&mut self, instr: &Instr) {
-        //println!("Evaluating: {:?}, pc: {}", instr, self.pc);
+    /// Decodes the instruction at `addr`, which may be the 4 byte F000 NNNN.
+    pub fn decode_instr(&self, addr: usize) -> Result<Instr> {
+        let opcode =
+            Opcode::new(self.read_word(addr)).with_operand(self.read_word(addr + INSTR_SIZE));
+
+        opcode
+            .try_into()
+            .map_err(|opcode| EmulatorError::UnknownOpcode { opcode, pc: addr })
+    }
+
+    fn read_word(&self, addr: usize) -> u16 {
+        let byte = |addr: usize| self.memory.get(addr).copied().unwrap_or(0) as u16;
+
+        byte(addr) << 8 | byte(addr + 1)
+    }
 
+    fn eval(&mut self, instr: &Instr) -> Result {
         match *instr {
             // Most interpreters ignore this
             Instr::JumpToMachineCode { addr: _ } => {
                 self.end_instr();
             }
             Instr::Clear => {
-                for pixel in self.display.iter_mut() {
-                    *pixel = false;
-                }
+                self.display.clear();
+
+                self.should_rerender = true;
+
+                self.end_instr();
+            }
+            Instr::ScrollDown { rows } => {
+                self.display.scroll_down(rows as usize);
+
+                self.should_rerender = true;
+
+                self.end_instr();
+            }
+            Instr::ScrollRight => {
+                self.display.scroll_right(SCROLL_COLUMNS);
+
+                self.should_rerender = true;
+
+                self.end_instr();
+            }
+            Instr::ScrollUp { rows } => {
+                self.display.scroll_up(rows as usize);
+
+                self.should_rerender = true;
+
+                self.end_instr();
+            }
+            Instr::ScrollLeft => {
+                self.display.scroll_left(SCROLL_COLUMNS);
+
+                self.should_rerender = true;
+
+                self.end_instr();
+            }
+            Instr::Exit => self.halted = true,
+            Instr::LoRes => {
+                self.display.set_hires(false);
+
+                self.should_rerender = true;
+
+                self.end_instr();
+            }
+            Instr::HiRes => {
+                self.display.set_hires(true);
 
                 self.should_rerender = true;
 
                 self.end_instr();
             }
             Instr::Return => {
+                if self.sp == 0 {
+                    return Err(EmulatorError::StackUnderflow { pc: self.pc });
+                }
+
                 self.sp -= 1;
 
                 self.pc = self.stack[self.sp];
             }
             Instr::Jump { addr } => self.pc = addr,
             Instr::Call { addr } => {
+                if self.sp == STACK_SIZE {
+                    return Err(EmulatorError::StackOverflow { pc: self.pc });
+                }
+
                 // After the call, skip the instruction after the present one
                 self.stack[self.sp] = self.pc + INSTR_SIZE;
 
@@ -153,6 +507,24 @@ impl Cpu {
                     self.end_instr();
                 }
             }
+            Instr::StoreRegisterRange { first, last } => {
+                self.check_memory(self.index, first.max(last) - first.min(last) + 1)?;
+
+                for (i, reg) in Cpu::register_range(first, last).enumerate() {
+                    self.memory[self.index + i] = self.registers[reg];
+                }
+
+                self.end_instr();
+            }
+            Instr::ReadRegisterRange { first, last } => {
+                self.check_memory(self.index, first.max(last) - first.min(last) + 1)?;
+
+                for (i, reg) in Cpu::register_range(first, last).enumerate() {
+                    self.registers[reg] = self.memory[self.index + i];
+                }
+
+                self.end_instr();
+            }
             Instr::RegisterSetLiteral { reg, lit } => {
                 self.registers[reg] = lit;
 
@@ -175,16 +547,22 @@ impl Cpu {
             Instr::RegisterSetRegisterBitwiseOr { left, right } => {
                 self.registers[left] |= self.registers[right];
 
+                self.reset_vf();
+
                 self.end_instr();
             }
             Instr::RegisterSetRegisterBitwiseAnd { left, right } => {
                 self.registers[left] &= self.registers[right];
 
+                self.reset_vf();
+
                 self.end_instr();
             }
             Instr::RegisterSetRegisterBitwiseXor { left, right } => {
                 self.registers[left] ^= self.registers[right];
 
+                self.reset_vf();
+
                 self.end_instr();
             }
             Instr::RegisterSetRegisterAdd { left, right } => {
@@ -213,10 +591,12 @@ impl Cpu {
 
                 self.end_instr();
             }
-            Instr::RegisterSetRegisterShr { left, right: _ } => {
-                self.registers[0xF] = self.registers[left] & 0x1;
+            Instr::RegisterSetRegisterShr { left, right } => {
+                let value = self.shift_operand(left, right);
+
+                self.registers[left] = value >> 1;
 
-                self.registers[left] >>= 1;
+                self.registers[0xF] = value & 0x1;
 
                 self.end_instr();
             }
@@ -233,10 +613,12 @@ impl Cpu {
 
                 self.end_instr();
             }
-            Instr::RegisterSetRegisterShl { reg } => {
-                self.registers[0xF] = self.registers[reg] >> 7;
+            Instr::RegisterSetRegisterShl { left, right } => {
+                let value = self.shift_operand(left, right);
 
-                self.registers[reg] <<= 1;
+                self.registers[left] = value << 1;
+
+                self.registers[0xF] = value >> 7;
 
                 self.end_instr();
             }
@@ -252,30 +634,53 @@ impl Cpu {
 
                 self.end_instr();
             }
-            Instr::JumpTo { addr } => self.pc = (self.registers[0] as usize) + addr,
+            Instr::SetIndexLong { value } => {
+                self.index = value;
+
+                self.pc += instr.size();
+            }
+            Instr::JumpTo { addr } => {
+                // With the jump quirk, BXNN jumps relative to VX instead of V0
+                let reg = if self.quirks.jump { addr >> 8 } else { 0 };
+
+                self.pc = (self.registers[reg] as usize) + addr;
+            }
             Instr::RandBitwiseAnd { reg, lit } => {
-                self.registers[reg] = rand::random::<u8>() & lit;
+                self.registers[reg] = self.rng.next_byte() & lit;
 
                 self.end_instr();
             }
             Instr::DrawSprite { x, y, size } => {
+                self.check_memory(self.index, self.sprite_len(size as usize, 8))?;
+
                 let x = self.registers[x] as usize;
 
                 let y = self.registers[y] as usize;
 
-                self.draw_sprite(x, y, size as usize);
+                self.draw_sprite(x, y, size as usize, 8);
+
+                self.end_instr();
+            }
+            Instr::DrawLargeSprite { x, y } => {
+                self.check_memory(self.index, self.sprite_len(16, 16))?;
+
+                let x = self.registers[x] as usize;
+
+                let y = self.registers[y] as usize;
+
+                self.draw_sprite(x, y, 16, 16);
 
                 self.end_instr();
             }
             Instr::SkipNextKeyPressed { reg } => {
-                if self.keys[self.registers[reg] as usize] {
+                if self.keys[self.registers[reg] as usize & 0xF] {
                     self.skip_instr();
                 } else {
                     self.end_instr();
                 }
             }
             Instr::SkipNextKeyNotPressed { reg } => {
-                if !self.keys[self.registers[reg] as usize] {
+                if !self.keys[self.registers[reg] as usize & 0xF] {
                     self.skip_instr();
                 } else {
                     self.end_instr();
@@ -286,6 +691,28 @@ impl Cpu {
 
                 self.end_instr();
             }
+            Instr::KeyPressWait { reg } if self.quirks.key_release => {
+                // Only a key pressed while waiting counts, not one still held from before
+                if self.key_wait.is_none() {
+                    self.key_wait = self.key_presses.iter().position(|&pressed| pressed);
+
+                    if let Some(key) = self.key_wait {
+                        self.key_presses[key] = false;
+
+                        self.key_releases[key] = false;
+                    }
+                }
+
+                let released = |key: &usize| !self.keys[*key] || self.key_releases[*key];
+
+                if let Some(key) = self.key_wait.filter(released) {
+                    self.registers[reg] = key as u8;
+
+                    self.key_wait = None;
+
+                    self.end_instr();
+                }
+            }
             Instr::KeyPressWait { reg } => {
                 // TODO: Try and use an iterator here
                 for (i, key) in self.keys.iter().enumerate() {
@@ -319,7 +746,15 @@ impl Cpu {
 
                 self.end_instr();
             }
+            Instr::SetIndexToBigDigitSprite { reg } => {
+                // * 10 because each big sprite is 10 bytes long
+                self.index = BIG_FONT_START + (self.registers[reg] as usize) * 10;
+
+                self.end_instr();
+            }
             Instr::StoreBCDAtIndex { reg } => {
+                self.check_memory(self.index, 3)?;
+
                 let reg = self.registers[reg];
 
                 let index = self.index;
@@ -336,53 +771,182 @@ impl Cpu {
                 self.end_instr();
             }
             Instr::StoreRegistersAtIndex { start_addr } => {
+                self.check_memory(self.index, start_addr + 1)?;
+
                 let regs = &self.registers[0..(start_addr + 1)];
 
                 let reg_end_addr = self.index + start_addr + 1;
 
                 self.memory[(self.index)..reg_end_addr].copy_from_slice(regs);
 
+                self.advance_index(start_addr);
+
                 self.end_instr();
             }
             Instr::ReadRegistersAtIndex { start_addr } => {
+                self.check_memory(self.index, start_addr + 1)?;
+
                 let reg_end_addr = (self.index + start_addr) + 1;
 
                 let memory_values = &self.memory[(self.index)..reg_end_addr];
 
                 self.registers[0..(start_addr) + 1].clone_from_slice(memory_values);
 
+                self.advance_index(start_addr);
+
+                self.end_instr();
+            }
+            Instr::SelectPlanes { planes } => {
+                self.display.select_planes(planes);
+
+                self.end_instr();
+            }
+            Instr::LoadAudioPattern => {
+                self.check_memory(self.index, AUDIO_PATTERN_SIZE)?;
+
+                let mut pattern = [0; AUDIO_PATTERN_SIZE];
+
+                pattern.copy_from_slice(&self.memory[self.index..self.index + AUDIO_PATTERN_SIZE]);
+
+                self.audio_pattern = Some(pattern);
+
+                self.end_instr();
+            }
+            Instr::SetPitchRegister { reg } => {
+                self.pitch = self.registers[reg];
+
+                self.end_instr();
+            }
+            Instr::StoreRegistersInFlags { last_reg } => {
+                self.flags[0..(last_reg + 1)].copy_from_slice(&self.registers[0..(last_reg + 1)]);
+
+                self.end_instr();
+            }
+            Instr::ReadRegistersFromFlags { last_reg } => {
+                self.registers[0..(last_reg + 1)].copy_from_slice(&self.flags[0..(last_reg + 1)]);
+
                 self.end_instr();
             }
         }
+
+        Ok(())
     }
 
-    fn draw_sprite(&mut self, x: usize, y: usize, size: usize) {
+    // Sprites are `width` pixels wide (8 or 16), stored as whole bytes per row.
+    // With both XO-CHIP planes selected, the second plane's sprite follows the first's.
+    fn draw_sprite(&mut self, x: usize, y: usize, height: usize, width: usize) {
         self.registers[0xF] = 0;
 
-        for i in 0..size {
-            let y = (y + i) % SCREEN_HEIGHT;
+        // See `Quirks::display_wait`
+        self.display_wait = self.quirks.display_wait;
 
-            for bit in 0..8 {
-                let x = (x + bit) % SCREEN_WIDTH;
+        let (screen_width, screen_height) = (self.display.width(), self.display.height());
 
-                let index = x + y * SCREEN_WIDTH;
+        let row_bytes = width / 8;
 
-                let colour = (self.memory[self.index + i]) >> (7 - bit) & 1;
+        // The starting position always wraps, only the sprite's body can be clipped
+        let (x, y) = (x % screen_width, y % screen_height);
 
-                self.registers[0xF] |= colour & (self.display[index]) as u8;
+        let mut sprite_addr = self.index;
 
-                self.display[index] ^= colour == 1;
+        for plane in 0..NUM_PLANES {
+            if self.display.planes() & (1 << plane) == 0 {
+                continue;
             }
+
+            for i in 0..height {
+                if self.quirks.clipping && y + i >= screen_height {
+                    break;
+                }
+
+                let y = (y + i) % screen_height;
+
+                for bit in 0..width {
+                    if self.quirks.clipping && x + bit >= screen_width {
+                        break;
+                    }
+
+                    let x = (x + bit) % screen_width;
+
+                    let byte = self.memory[sprite_addr + i * row_bytes + bit / 8];
+
+                    if (byte >> (7 - bit % 8)) & 1 == 1 && self.display.toggle(x, y, plane) {
+                        self.registers[0xF] = 1;
+                    }
+                }
+            }
+
+            sprite_addr += height * row_bytes;
         }
 
         self.should_rerender = true;
     }
 
+    // The bytes a sprite reads, which double when both XO-CHIP planes are selected
+    fn sprite_len(&self, height: usize, width: usize) -> usize {
+        let planes = self.display.planes().count_ones() as usize;
+
+        height * width / 8 * planes
+    }
+
+    // Faults unless `len` bytes from `addr` are all inside the platform's memory
+    fn check_memory(&self, addr: usize, len: usize) -> Result {
+        if addr + len > self.memory_size {
+            return Err(EmulatorError::MemoryOutOfBounds {
+                addr: addr.max(self.memory_size),
+                pc: self.pc,
+            });
+        }
+
+        Ok(())
+    }
+
+    // 5XY2/5XY3 walk from X to Y, backwards if Y is below X
+    fn register_range(first: usize, last: usize) -> Box<dyn Iterator<Item = usize>> {
+        if first <= last {
+            Box::new(first..=last)
+        } else {
+            Box::new((last..=first).rev())
+        }
+    }
+
+    fn shift_operand(&self, left: usize, right: usize) -> u8 {
+        if self.quirks.shift {
+            self.registers[left]
+        } else {
+            self.registers[right]
+        }
+    }
+
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    fn advance_index(&mut self, last_reg: usize) {
+        if !self.quirks.load_store {
+            self.index += last_reg + 1;
+        }
+    }
+
     fn end_instr(&mut self) {
         self.pc += INSTR_SIZE;
     }
 
+    // Skips over the whole of the next instruction, even if it's a 4 byte F000 NNNN
     fn skip_instr(&mut self) {
-        self.pc += INSTR_SIZE * 2;
+        let next = self.pc + INSTR_SIZE;
+
+        self.pc = next
+            + self
+                .decode_instr(next)
+                .map_or(INSTR_SIZE, |instr| instr.size());
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::new()
     }
 }
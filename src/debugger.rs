@@ -0,0 +1,109 @@
+use crate::{instr::Instr, machine::Machine, Result};
+use std::collections::BTreeSet;
+
+/// Pauses, steps and breaks a [`Machine`] on behalf of a frontend's debugging UI.
+#[derive(Clone, Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    paused: bool,
+    // The return address and stack depth to stop at when stepping over a call
+    step_over: Option<(usize, usize)>,
+    // Set on resuming, so the instruction we stopped at doesn't stop us again
+    resuming: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+
+        self.step_over = None;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+
+        self.resuming = true;
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Returns whether there was a breakpoint at `addr` to remove.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    /// Executes a single instruction while paused, first idling through any
+    /// stall after a draw.
+    pub fn step(&mut self, machine: &mut Machine) -> Result {
+        while machine.stalled() {
+            machine.step()?;
+        }
+
+        machine.step()
+    }
+
+    /// Steps, but runs a whole subroutine if the next instruction calls one.
+    pub fn step_over(&mut self, machine: &mut Machine) -> Result {
+        let cpu = machine.cpu();
+
+        match cpu.decode_instr(cpu.pc()) {
+            Ok(Instr::Call { .. }) => {
+                self.step_over = Some((cpu.pc() + 2, cpu.sp()));
+
+                self.resume();
+
+                Ok(())
+            }
+            _ => self.step(machine),
+        }
+    }
+
+    /// Runs the rest of the frame unless paused, returning whether a breakpoint
+    /// (or the end of a step over) paused execution part way through.
+    ///
+    /// Faults are passed back for the frontend to decide what to do with.
+    pub fn run_frame(&mut self, machine: &mut Machine) -> Result<bool> {
+        if self.paused {
+            return Ok(false);
+        }
+
+        let frame = machine.frames();
+
+        while machine.frames() == frame {
+            // A stalled CPU stays on the next instruction without reaching it
+            if !machine.stalled() && self.should_stop(machine) {
+                self.pause();
+
+                return Ok(true);
+            }
+
+            machine.step()?;
+        }
+
+        Ok(false)
+    }
+
+    fn should_stop(&mut self, machine: &Machine) -> bool {
+        if std::mem::take(&mut self.resuming) {
+            return false;
+        }
+
+        let (pc, sp) = (machine.cpu().pc(), machine.cpu().sp());
+
+        self.step_over == Some((pc, sp)) || self.breakpoints.contains(&pc)
+    }
+}
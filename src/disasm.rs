@@ -0,0 +1,427 @@
+use crate::{cpu::INSTR_START, instr::Instr, opcode::Opcode};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryInto,
+    fmt,
+    str::FromStr,
+};
+
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// Octo's assembly language, which the assembler reads back in.
+    Octo,
+    /// The mnemonics from Cowgod's CHIP-8 technical reference.
+    Cowgod,
+}
+
+impl Syntax {
+    fn comment(self) -> &'static str {
+        match self {
+            Syntax::Octo => "#",
+            Syntax::Cowgod => ";",
+        }
+    }
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Syntax::Octo => f.write_str("octo"),
+            Syntax::Cowgod => f.write_str("cowgod"),
+        }
+    }
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "octo" => Ok(Syntax::Octo),
+            "cowgod" => Ok(Syntax::Cowgod),
+            _ => Err(format!("unknown syntax '{}', expected octo or cowgod", s)),
+        }
+    }
+}
+
+// What a run of ROM bytes turned out to be
+#[derive(Copy, Clone, Debug)]
+enum Item {
+    Code(Instr),
+    // Reached by the program but not a valid instruction
+    Unknown(u16),
+    Data,
+}
+
+/// Disassembles a ROM loaded at 0x200 into a listing with labels and addresses.
+///
+/// Jumps and calls are followed from the entry point to tell code from data,
+/// so bytes the program never executes (such as sprites) come out as data.
+pub fn disassemble(rom: &[u8], syntax: Syntax) -> String {
+    let end = INSTR_START + rom.len();
+
+    let word = |addr: usize| {
+        let byte = |addr: usize| rom.get(addr - INSTR_START).copied().unwrap_or(0) as u16;
+
+        byte(addr) << 8 | byte(addr + 1)
+    };
+
+    let mut decoded = BTreeMap::new();
+
+    let mut calls = BTreeSet::new();
+
+    let mut jumps = BTreeSet::new();
+
+    let mut pending = vec![INSTR_START];
+
+    while let Some(addr) = pending.pop() {
+        if addr < INSTR_START || addr + 1 >= end || decoded.contains_key(&addr) {
+            continue;
+        }
+
+        let opcode = Opcode::new(word(addr)).with_operand(word(addr + 2));
+
+        let instr: Instr = match opcode.try_into() {
+            Ok(instr) => instr,
+            Err(_) => {
+                decoded.insert(addr, Item::Unknown(opcode.raw()));
+
+                continue;
+            }
+        };
+
+        decoded.insert(addr, Item::Code(instr));
+
+        let next = addr + instr.size();
+
+        match instr {
+            Instr::Jump { addr } => {
+                jumps.insert(addr);
+
+                pending.push(addr);
+            }
+            Instr::Call { addr } => {
+                calls.insert(addr);
+
+                pending.push(addr);
+
+                pending.push(next);
+            }
+            // The target of BNNN depends on a register, so it can't be followed
+            Instr::Return | Instr::Exit | Instr::JumpTo { .. } => {}
+            Instr::SkipNextEqualLiteral { .. }
+            | Instr::SkipNextNotEqualLiteral { .. }
+            | Instr::SkipNextEqualRegister { .. }
+            | Instr::SkipNextNotEqualRegister { .. }
+            | Instr::SkipNextKeyPressed { .. }
+            | Instr::SkipNextKeyNotPressed { .. } => {
+                let skipped: Result<Instr, _> = Opcode::new(word(next)).try_into();
+
+                let skipped = skipped.map_or(2, |instr| instr.size());
+
+                pending.push(next);
+
+                pending.push(next + skipped);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    // Lay the ROM out as instructions and data, skipping instructions which
+    // overlap ones already placed (as happens with self-modifying code)
+    let mut items = BTreeMap::new();
+
+    let mut addr = INSTR_START;
+
+    while addr < end {
+        match decoded.get(&addr) {
+            Some(&Item::Code(instr)) if addr + instr.size() <= end => {
+                items.insert(addr, Item::Code(instr));
+
+                addr += instr.size();
+            }
+            Some(&Item::Unknown(raw)) => {
+                items.insert(addr, Item::Unknown(raw));
+
+                addr += 2;
+            }
+            _ => {
+                items.insert(addr, Item::Data);
+
+                addr += 1;
+            }
+        }
+    }
+
+    let data_refs = items.values().filter_map(|item| match item {
+        Item::Code(Instr::SetIndex { value }) | Item::Code(Instr::SetIndexLong { value }) => {
+            Some(*value)
+        }
+        _ => None,
+    });
+
+    // Only addresses which start a line can be given a label
+    let mut labels = BTreeMap::new();
+
+    labels.insert(INSTR_START, "main".to_string());
+
+    for addr in data_refs
+        .chain(jumps.iter().copied())
+        .chain(calls.iter().copied())
+    {
+        if addr == INSTR_START || !items.contains_key(&addr) {
+            continue;
+        }
+
+        let prefix = match items[&addr] {
+            Item::Code(_) if calls.contains(&addr) => "sub",
+            Item::Code(_) => "label",
+            _ => "data",
+        };
+
+        labels.insert(addr, format!("{}_{:03x}", prefix, addr));
+    }
+
+    let name = |addr: usize| match labels.get(&addr) {
+        Some(label) => label.clone(),
+        None => format!("{:#05X}", addr),
+    };
+
+    let mut out = String::new();
+
+    let mut data = Vec::new();
+
+    for (&addr, item) in items.iter() {
+        let labelled = labels.contains_key(&addr);
+
+        // Data lines run until they're full, reach a label or reach code
+        let flush = match item {
+            Item::Data => labelled || data.len() == DATA_PER_LINE,
+            _ => true,
+        };
+
+        if flush && !data.is_empty() {
+            out += &data_line(&data, syntax, rom);
+
+            data.clear();
+        }
+
+        if let Some(label) = labels.get(&addr) {
+            out += &match syntax {
+                Syntax::Octo => format!(": {}\n", label),
+                Syntax::Cowgod => format!("{}:\n", label),
+            };
+        }
+
+        match item {
+            Item::Code(instr) => {
+                let bytes = &rom[addr - INSTR_START..addr - INSTR_START + instr.size()];
+
+                let raw: Vec<_> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+
+                let text = mnemonic(instr, syntax, &name);
+
+                // Opcodes with bits the decoder ignores (like 9XY1) are kept as
+                // bytes, so assembling the listing gives back the same ROM
+                out += &if instr.encode() == bytes {
+                    line(&text, syntax, addr, &raw.concat())
+                } else {
+                    let addrs: Vec<_> = (addr..addr + instr.size()).collect();
+
+                    let comment = format!("{} {}", raw.concat(), text);
+
+                    line(&data_bytes(&addrs, syntax, rom), syntax, addr, &comment)
+                };
+            }
+            Item::Unknown(raw) => {
+                let bytes = data_bytes(&[addr, addr + 1], syntax, rom);
+
+                let comment = format!("{:04X} unknown opcode", raw);
+
+                out += &line(&bytes, syntax, addr, &comment);
+            }
+            Item::Data => data.push(addr),
+        }
+    }
+
+    if !data.is_empty() {
+        out += &data_line(&data, syntax, rom);
+    }
+
+    out
+}
+
+/// Formats an instruction, using `name` to turn addresses into labels.
+pub fn mnemonic(instr: &Instr, syntax: Syntax, name: &dyn Fn(usize) -> String) -> String {
+    match syntax {
+        Syntax::Octo => octo(instr, name),
+        Syntax::Cowgod => cowgod(instr, name),
+    }
+}
+
+fn line(text: &str, syntax: Syntax, addr: usize, comment: &str) -> String {
+    format!(
+        "    {:<28} {} {:#05X}  {}\n",
+        text,
+        syntax.comment(),
+        addr,
+        comment
+    )
+}
+
+fn data_line(addrs: &[usize], syntax: Syntax, rom: &[u8]) -> String {
+    line(&data_bytes(addrs, syntax, rom), syntax, addrs[0], "data")
+}
+
+fn data_bytes(addrs: &[usize], syntax: Syntax, rom: &[u8]) -> String {
+    let bytes: Vec<_> = addrs
+        .iter()
+        .map(|addr| format!("{:#04X}", rom[addr - INSTR_START]))
+        .collect();
+
+    match syntax {
+        Syntax::Octo => bytes.join(" "),
+        Syntax::Cowgod => format!("DB {}", bytes.join(", ")),
+    }
+}
+
+fn octo(instr: &Instr, name: &dyn Fn(usize) -> String) -> String {
+    match *instr {
+        // Octo has no mnemonic for machine code calls, so emit the raw bytes
+        Instr::JumpToMachineCode { addr } => format!("{:#04X} {:#04X}", addr >> 8, addr & 0xFF),
+        Instr::Clear => "clear".to_string(),
+        Instr::Return => "return".to_string(),
+        Instr::ScrollDown { rows } => format!("scroll-down {}", rows),
+        Instr::ScrollUp { rows } => format!("scroll-up {}", rows),
+        Instr::ScrollRight => "scroll-right".to_string(),
+        Instr::ScrollLeft => "scroll-left".to_string(),
+        Instr::Exit => "exit".to_string(),
+        Instr::LoRes => "lores".to_string(),
+        Instr::HiRes => "hires".to_string(),
+        Instr::Jump { addr } => format!("jump {}", name(addr)),
+        Instr::Call { addr } => format!(":call {}", name(addr)),
+        // Octo's conditionals say when the next instruction runs, not when it's skipped
+        Instr::SkipNextEqualLiteral { reg, lit } => format!("if v{:x} != {:#04X} then", reg, lit),
+        Instr::SkipNextNotEqualLiteral { reg, lit } => {
+            format!("if v{:x} == {:#04X} then", reg, lit)
+        }
+        Instr::SkipNextEqualRegister { left, right } => {
+            format!("if v{:x} != v{:x} then", left, right)
+        }
+        Instr::StoreRegisterRange { first, last } => format!("save v{:x} - v{:x}", first, last),
+        Instr::ReadRegisterRange { first, last } => format!("load v{:x} - v{:x}", first, last),
+        Instr::RegisterSetLiteral { reg, lit } => format!("v{:x} := {:#04X}", reg, lit),
+        Instr::RegisterAddAssign { reg, lit } => format!("v{:x} += {:#04X}", reg, lit),
+        Instr::RegisterSetRegister { left, right } => format!("v{:x} := v{:x}", left, right),
+        Instr::RegisterSetRegisterBitwiseOr { left, right } => {
+            format!("v{:x} |= v{:x}", left, right)
+        }
+        Instr::RegisterSetRegisterBitwiseAnd { left, right } => {
+            format!("v{:x} &= v{:x}", left, right)
+        }
+        Instr::RegisterSetRegisterBitwiseXor { left, right } => {
+            format!("v{:x} ^= v{:x}", left, right)
+        }
+        Instr::RegisterSetRegisterAdd { left, right } => format!("v{:x} += v{:x}", left, right),
+        Instr::RegisterSetRegisterSub { left, right } => format!("v{:x} -= v{:x}", left, right),
+        Instr::RegisterSetRegisterShr { left, right } => format!("v{:x} >>= v{:x}", left, right),
+        Instr::RegisterSetRegisterSubn { left, right } => format!("v{:x} =- v{:x}", left, right),
+        Instr::RegisterSetRegisterShl { left, right } => format!("v{:x} <<= v{:x}", left, right),
+        Instr::SkipNextNotEqualRegister { left, right } => {
+            format!("if v{:x} == v{:x} then", left, right)
+        }
+        Instr::SetIndex { value } => format!("i := {}", name(value)),
+        Instr::SetIndexLong { value } => format!("i := long {}", name(value)),
+        Instr::JumpTo { addr } => format!("jump0 {}", name(addr)),
+        Instr::RandBitwiseAnd { reg, lit } => format!("v{:x} := random {:#04X}", reg, lit),
+        Instr::DrawSprite { x, y, size } => format!("sprite v{:x} v{:x} {}", x, y, size),
+        Instr::DrawLargeSprite { x, y } => format!("sprite v{:x} v{:x} 0", x, y),
+        Instr::SkipNextKeyPressed { reg } => format!("if v{:x} -key then", reg),
+        Instr::SkipNextKeyNotPressed { reg } => format!("if v{:x} key then", reg),
+        Instr::SetDelayTimerValue { reg } => format!("v{:x} := delay", reg),
+        Instr::KeyPressWait { reg } => format!("v{:x} := key", reg),
+        Instr::SetDelayTimerRegister { reg } => format!("delay := v{:x}", reg),
+        Instr::SetSoundTimerRegister { reg } => format!("buzzer := v{:x}", reg),
+        Instr::IndexAddAssignRegister { reg } => format!("i += v{:x}", reg),
+        Instr::SetIndexToDigitSprite { reg } => format!("i := hex v{:x}", reg),
+        Instr::SetIndexToBigDigitSprite { reg } => format!("i := bighex v{:x}", reg),
+        Instr::StoreBCDAtIndex { reg } => format!("bcd v{:x}", reg),
+        Instr::StoreRegistersAtIndex { start_addr } => format!("save v{:x}", start_addr),
+        Instr::ReadRegistersAtIndex { start_addr } => format!("load v{:x}", start_addr),
+        Instr::SelectPlanes { planes } => format!("plane {}", planes),
+        Instr::LoadAudioPattern => "audio".to_string(),
+        Instr::SetPitchRegister { reg } => format!("pitch := v{:x}", reg),
+        Instr::StoreRegistersInFlags { last_reg } => format!("saveflags v{:x}", last_reg),
+        Instr::ReadRegistersFromFlags { last_reg } => format!("loadflags v{:x}", last_reg),
+    }
+}
+
+// Cowgod's mnemonics, extended with the usual SUPER-CHIP and XO-CHIP names
+fn cowgod(instr: &Instr, name: &dyn Fn(usize) -> String) -> String {
+    match *instr {
+        Instr::JumpToMachineCode { addr } => format!("SYS {}", name(addr)),
+        Instr::Clear => "CLS".to_string(),
+        Instr::Return => "RET".to_string(),
+        Instr::ScrollDown { rows } => format!("SCD {}", rows),
+        Instr::ScrollUp { rows } => format!("SCU {}", rows),
+        Instr::ScrollRight => "SCR".to_string(),
+        Instr::ScrollLeft => "SCL".to_string(),
+        Instr::Exit => "EXIT".to_string(),
+        Instr::LoRes => "LOW".to_string(),
+        Instr::HiRes => "HIGH".to_string(),
+        Instr::Jump { addr } => format!("JP {}", name(addr)),
+        Instr::Call { addr } => format!("CALL {}", name(addr)),
+        Instr::SkipNextEqualLiteral { reg, lit } => format!("SE V{:X}, {:#04X}", reg, lit),
+        Instr::SkipNextNotEqualLiteral { reg, lit } => format!("SNE V{:X}, {:#04X}", reg, lit),
+        Instr::SkipNextEqualRegister { left, right } => format!("SE V{:X}, V{:X}", left, right),
+        Instr::StoreRegisterRange { first, last } => format!("LD [I], V{:X}-V{:X}", first, last),
+        Instr::ReadRegisterRange { first, last } => format!("LD V{:X}-V{:X}, [I]", first, last),
+        Instr::RegisterSetLiteral { reg, lit } => format!("LD V{:X}, {:#04X}", reg, lit),
+        Instr::RegisterAddAssign { reg, lit } => format!("ADD V{:X}, {:#04X}", reg, lit),
+        Instr::RegisterSetRegister { left, right } => format!("LD V{:X}, V{:X}", left, right),
+        Instr::RegisterSetRegisterBitwiseOr { left, right } => {
+            format!("OR V{:X}, V{:X}", left, right)
+        }
+        Instr::RegisterSetRegisterBitwiseAnd { left, right } => {
+            format!("AND V{:X}, V{:X}", left, right)
+        }
+        Instr::RegisterSetRegisterBitwiseXor { left, right } => {
+            format!("XOR V{:X}, V{:X}", left, right)
+        }
+        Instr::RegisterSetRegisterAdd { left, right } => format!("ADD V{:X}, V{:X}", left, right),
+        Instr::RegisterSetRegisterSub { left, right } => format!("SUB V{:X}, V{:X}", left, right),
+        Instr::RegisterSetRegisterShr { left, right } => format!("SHR V{:X}, V{:X}", left, right),
+        Instr::RegisterSetRegisterSubn { left, right } => {
+            format!("SUBN V{:X}, V{:X}", left, right)
+        }
+        Instr::RegisterSetRegisterShl { left, right } => format!("SHL V{:X}, V{:X}", left, right),
+        Instr::SkipNextNotEqualRegister { left, right } => {
+            format!("SNE V{:X}, V{:X}", left, right)
+        }
+        Instr::SetIndex { value } => format!("LD I, {}", name(value)),
+        Instr::SetIndexLong { value } => format!("LD I, LONG {}", name(value)),
+        Instr::JumpTo { addr } => format!("JP V0, {}", name(addr)),
+        Instr::RandBitwiseAnd { reg, lit } => format!("RND V{:X}, {:#04X}", reg, lit),
+        Instr::DrawSprite { x, y, size } => format!("DRW V{:X}, V{:X}, {}", x, y, size),
+        Instr::DrawLargeSprite { x, y } => format!("DRW V{:X}, V{:X}, 0", x, y),
+        Instr::SkipNextKeyPressed { reg } => format!("SKP V{:X}", reg),
+        Instr::SkipNextKeyNotPressed { reg } => format!("SKNP V{:X}", reg),
+        Instr::SetDelayTimerValue { reg } => format!("LD V{:X}, DT", reg),
+        Instr::KeyPressWait { reg } => format!("LD V{:X}, K", reg),
+        Instr::SetDelayTimerRegister { reg } => format!("LD DT, V{:X}", reg),
+        Instr::SetSoundTimerRegister { reg } => format!("LD ST, V{:X}", reg),
+        Instr::IndexAddAssignRegister { reg } => format!("ADD I, V{:X}", reg),
+        Instr::SetIndexToDigitSprite { reg } => format!("LD F, V{:X}", reg),
+        Instr::SetIndexToBigDigitSprite { reg } => format!("LD HF, V{:X}", reg),
+        Instr::StoreBCDAtIndex { reg } => format!("LD B, V{:X}", reg),
+        Instr::StoreRegistersAtIndex { start_addr } => format!("LD [I], V{:X}", start_addr),
+        Instr::ReadRegistersAtIndex { start_addr } => format!("LD V{:X}, [I]", start_addr),
+        Instr::SelectPlanes { planes } => format!("PLANE {}", planes),
+        Instr::LoadAudioPattern => "AUDIO".to_string(),
+        Instr::SetPitchRegister { reg } => format!("PITCH V{:X}", reg),
+        Instr::StoreRegistersInFlags { last_reg } => format!("LD R, V{:X}", last_reg),
+        Instr::ReadRegistersFromFlags { last_reg } => format!("LD V{:X}, R", last_reg),
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    state::{StateReader, StateWriter},
+    Result,
+};
+
+pub const SCREEN_WIDTH: usize = 64;
+
+pub const SCREEN_HEIGHT: usize = 32;
+
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+
+/// The number of XO-CHIP bitplanes, giving four colours per pixel.
+pub const NUM_PLANES: usize = 2;
+
+// Characters for the background, plane 1, plane 2 and both planes
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+/// The framebuffer, which is 64x32 in lo-res mode and 128x64 in SUPER-CHIP hi-res mode.
+///
+/// Each pixel holds one bit per XO-CHIP bitplane, so its value is a colour from 0 to 3.
+/// Plain CHIP-8 programs only ever draw to the first plane.
+#[derive(Clone, Debug)]
+pub struct Display {
+    pixels: [u8; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT],
+    planes: u8,
+    hires: bool,
+}
+
+impl Display {
+    pub fn new() -> Self {
+        Display {
+            pixels: [0; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT],
+            planes: 0b01,
+            hires: false,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_SCREEN_WIDTH
+        } else {
+            SCREEN_WIDTH
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_SCREEN_HEIGHT
+        } else {
+            SCREEN_HEIGHT
+        }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// The bitmask of planes that drawing, clearing and scrolling affect.
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// The visible pixels' colours, row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels[..self.width() * self.height()]
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[x + y * self.width()]
+    }
+
+    /// Draws the visible pixels as text, a line per row, with `.` for the
+    /// background, `#` and `+` for the first and second planes and `@` for both.
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::with_capacity((self.width() + 1) * self.height());
+
+        for row in self.pixels().chunks(self.width()) {
+            text.extend(row.iter().map(|&pixel| ASCII_PIXELS[pixel as usize]));
+
+            text.push('\n');
+        }
+
+        text
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.pixels);
+
+        writer.u8(self.planes);
+
+        writer.bool(self.hires);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result {
+        reader.fill(&mut self.pixels)?;
+
+        self.planes = reader.u8()? & 0b11;
+
+        self.hires = reader.bool()?;
+
+        Ok(())
+    }
+
+    pub(crate) fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+
+        // Changing resolution wipes every plane, not just the selected ones
+        self.pixels = [0; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT];
+    }
+
+    pub(crate) fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & 0b11;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !self.planes;
+        }
+    }
+
+    /// Flips a pixel on one plane, returning whether it was switched off.
+    pub(crate) fn toggle(&mut self, x: usize, y: usize, plane: usize) -> bool {
+        let index = x + y * self.width();
+
+        let bit = 1 << plane;
+
+        self.pixels[index] ^= bit;
+
+        self.pixels[index] & bit == 0
+    }
+
+    pub(crate) fn scroll_down(&mut self, rows: usize) {
+        self.shift(0, rows as isize);
+    }
+
+    pub(crate) fn scroll_up(&mut self, rows: usize) {
+        self.shift(0, -(rows as isize));
+    }
+
+    pub(crate) fn scroll_left(&mut self, columns: usize) {
+        self.shift(-(columns as isize), 0);
+    }
+
+    pub(crate) fn scroll_right(&mut self, columns: usize) {
+        self.shift(columns as isize, 0);
+    }
+
+    // Moves the selected planes' contents, filling the exposed area with blank pixels
+    fn shift(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+
+        let source = self.pixels;
+
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+
+                let moved = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    source[(src_x + src_y * width) as usize]
+                } else {
+                    0
+                };
+
+                let index = (x + y * width) as usize;
+
+                self.pixels[index] = (source[index] & !self.planes) | (moved & self.planes);
+            }
+        }
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Display::new()
+    }
+}
@@ -1,13 +1,25 @@
+use crate::disasm::{self, Syntax};
+use std::fmt;
+
 #[derive(Copy, Clone, Debug)]
-pub(crate) enum Instr {
+pub enum Instr {
     JumpToMachineCode { addr: usize },
     Clear,
     Return,
+    ScrollDown { rows: u8 },
+    ScrollUp { rows: u8 },
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LoRes,
+    HiRes,
     Jump { addr: usize },
     Call { addr: usize },
     SkipNextEqualLiteral { reg: usize, lit: u8 },
     SkipNextNotEqualLiteral { reg: usize, lit: u8 },
     SkipNextEqualRegister { left: usize, right: usize },
+    StoreRegisterRange { first: usize, last: usize },
+    ReadRegisterRange { first: usize, last: usize },
     RegisterSetLiteral { reg: usize, lit: u8 },
     RegisterAddAssign { reg: usize, lit: u8 },
     RegisterSetRegister { left: usize, right: usize },
@@ -18,12 +30,14 @@ pub(crate) enum Instr {
     RegisterSetRegisterSub { left: usize, right: usize },
     RegisterSetRegisterShr { left: usize, right: usize },
     RegisterSetRegisterSubn { left: usize, right: usize },
-    RegisterSetRegisterShl { reg: usize },
+    RegisterSetRegisterShl { left: usize, right: usize },
     SkipNextNotEqualRegister { left: usize, right: usize },
     SetIndex { value: usize },
+    SetIndexLong { value: usize },
     JumpTo { addr: usize },
     RandBitwiseAnd { reg: usize, lit: u8 },
     DrawSprite { x: usize, y: usize, size: u8 },
+    DrawLargeSprite { x: usize, y: usize },
     SkipNextKeyPressed { reg: usize },
     SkipNextKeyNotPressed { reg: usize },
     SetDelayTimerValue { reg: usize },
@@ -32,7 +46,99 @@ pub(crate) enum Instr {
     SetSoundTimerRegister { reg: usize },
     IndexAddAssignRegister { reg: usize },
     SetIndexToDigitSprite { reg: usize },
+    SetIndexToBigDigitSprite { reg: usize },
     StoreBCDAtIndex { reg: usize },
     StoreRegistersAtIndex { start_addr: usize },
     ReadRegistersAtIndex { start_addr: usize },
+    SelectPlanes { planes: u8 },
+    LoadAudioPattern,
+    SetPitchRegister { reg: usize },
+    StoreRegistersInFlags { last_reg: usize },
+    ReadRegistersFromFlags { last_reg: usize },
+}
+
+impl Instr {
+    /// The instruction's length in bytes, which is 4 for F000 NNNN and 2 otherwise.
+    pub fn size(&self) -> usize {
+        match self {
+            Instr::SetIndexLong { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    /// Encodes the instruction back into its big-endian opcode bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let xy = |op: u16, x: usize, y: usize, n: u16| op | (x as u16) << 8 | (y as u16) << 4 | n;
+
+        let xkk = |op: u16, x: usize, kk: u8| op | (x as u16) << 8 | kk as u16;
+
+        let nnn = |op: u16, nnn: usize| op | (nnn as u16 & 0x0FFF);
+
+        let opcode = match *self {
+            Instr::JumpToMachineCode { addr } => nnn(0x0000, addr),
+            Instr::Clear => 0x00E0,
+            Instr::Return => 0x00EE,
+            Instr::ScrollDown { rows } => 0x00C0 | (rows as u16 & 0xF),
+            Instr::ScrollUp { rows } => 0x00D0 | (rows as u16 & 0xF),
+            Instr::ScrollRight => 0x00FB,
+            Instr::ScrollLeft => 0x00FC,
+            Instr::Exit => 0x00FD,
+            Instr::LoRes => 0x00FE,
+            Instr::HiRes => 0x00FF,
+            Instr::Jump { addr } => nnn(0x1000, addr),
+            Instr::Call { addr } => nnn(0x2000, addr),
+            Instr::SkipNextEqualLiteral { reg, lit } => xkk(0x3000, reg, lit),
+            Instr::SkipNextNotEqualLiteral { reg, lit } => xkk(0x4000, reg, lit),
+            Instr::SkipNextEqualRegister { left, right } => xy(0x5000, left, right, 0x0),
+            Instr::StoreRegisterRange { first, last } => xy(0x5000, first, last, 0x2),
+            Instr::ReadRegisterRange { first, last } => xy(0x5000, first, last, 0x3),
+            Instr::RegisterSetLiteral { reg, lit } => xkk(0x6000, reg, lit),
+            Instr::RegisterAddAssign { reg, lit } => xkk(0x7000, reg, lit),
+            Instr::RegisterSetRegister { left, right } => xy(0x8000, left, right, 0x0),
+            Instr::RegisterSetRegisterBitwiseOr { left, right } => xy(0x8000, left, right, 0x1),
+            Instr::RegisterSetRegisterBitwiseAnd { left, right } => xy(0x8000, left, right, 0x2),
+            Instr::RegisterSetRegisterBitwiseXor { left, right } => xy(0x8000, left, right, 0x3),
+            Instr::RegisterSetRegisterAdd { left, right } => xy(0x8000, left, right, 0x4),
+            Instr::RegisterSetRegisterSub { left, right } => xy(0x8000, left, right, 0x5),
+            Instr::RegisterSetRegisterShr { left, right } => xy(0x8000, left, right, 0x6),
+            Instr::RegisterSetRegisterSubn { left, right } => xy(0x8000, left, right, 0x7),
+            Instr::RegisterSetRegisterShl { left, right } => xy(0x8000, left, right, 0xE),
+            Instr::SkipNextNotEqualRegister { left, right } => xy(0x9000, left, right, 0x0),
+            Instr::SetIndex { value } => nnn(0xA000, value),
+            Instr::SetIndexLong { value } => {
+                return vec![0xF0, 0x00, (value >> 8) as u8, value as u8];
+            }
+            Instr::JumpTo { addr } => nnn(0xB000, addr),
+            Instr::RandBitwiseAnd { reg, lit } => xkk(0xC000, reg, lit),
+            Instr::DrawSprite { x, y, size } => xy(0xD000, x, y, size as u16 & 0xF),
+            Instr::DrawLargeSprite { x, y } => xy(0xD000, x, y, 0x0),
+            Instr::SkipNextKeyPressed { reg } => xkk(0xE000, reg, 0x9E),
+            Instr::SkipNextKeyNotPressed { reg } => xkk(0xE000, reg, 0xA1),
+            Instr::SetDelayTimerValue { reg } => xkk(0xF000, reg, 0x07),
+            Instr::KeyPressWait { reg } => xkk(0xF000, reg, 0x0A),
+            Instr::SetDelayTimerRegister { reg } => xkk(0xF000, reg, 0x15),
+            Instr::SetSoundTimerRegister { reg } => xkk(0xF000, reg, 0x18),
+            Instr::IndexAddAssignRegister { reg } => xkk(0xF000, reg, 0x1E),
+            Instr::SetIndexToDigitSprite { reg } => xkk(0xF000, reg, 0x29),
+            Instr::SetIndexToBigDigitSprite { reg } => xkk(0xF000, reg, 0x30),
+            Instr::StoreBCDAtIndex { reg } => xkk(0xF000, reg, 0x33),
+            Instr::StoreRegistersAtIndex { start_addr } => xkk(0xF000, start_addr, 0x55),
+            Instr::ReadRegistersAtIndex { start_addr } => xkk(0xF000, start_addr, 0x65),
+            Instr::SelectPlanes { planes } => xkk(0xF000, planes as usize, 0x01),
+            Instr::LoadAudioPattern => 0xF002,
+            Instr::SetPitchRegister { reg } => xkk(0xF000, reg, 0x3A),
+            Instr::StoreRegistersInFlags { last_reg } => xkk(0xF000, last_reg, 0x75),
+            Instr::ReadRegistersFromFlags { last_reg } => xkk(0xF000, last_reg, 0x85),
+        };
+
+        opcode.to_be_bytes().to_vec()
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |addr: usize| format!("{:#05X}", addr);
+
+        f.write_str(&disasm::mnemonic(self, Syntax::Cowgod, &name))
+    }
 }
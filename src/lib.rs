@@ -0,0 +1,95 @@
+//! A CHIP-8 interpreter core with no windowing or command line dependencies.
+//!
+//! [`Machine`] is the main entry point: load a ROM into it, feed it key
+//! presses and step it, then read the display, registers and memory back out.
+
+pub use crate::{
+    asm::assemble,
+    audio::{
+        AudioSink, Beeper, MemorySink, DEFAULT_FREQUENCY, DEFAULT_SAMPLE_RATE, DEFAULT_VOLUME,
+    },
+    cpu::{
+        Cpu, AUDIO_PATTERN_SIZE, DEFAULT_PITCH, MAX_INSTRS, MEM_SIZE, NUM_FLAGS, NUM_KEYS, REGS,
+        STACK_SIZE,
+    },
+    debugger::Debugger,
+    disasm::{disassemble, Syntax},
+    display::{
+        Display, HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH, NUM_PLANES, SCREEN_HEIGHT, SCREEN_WIDTH,
+    },
+    instr::Instr,
+    machine::{Machine, DEFAULT_CYCLES_PER_FRAME, FRAME_RATE},
+    movie::{Movie, MOVIE_VERSION},
+    opcode::Opcode,
+    palette::{Palette, Theme, NUM_COLOURS},
+    quirks::{Platform, Quirks},
+    rewind::{RewindBuffer, DEFAULT_REWIND_FRAMES},
+    rng::{Rng, RngAlgorithm},
+    state::{rom_hash, STATE_VERSION},
+};
+
+use std::fmt;
+
+mod asm;
+mod audio;
+mod cpu;
+mod debugger;
+mod disasm;
+mod display;
+mod fonts;
+mod instr;
+mod machine;
+mod movie;
+mod opcode;
+mod palette;
+mod quirks;
+mod rewind;
+mod rng;
+mod state;
+
+pub type Result<T = ()> = std::result::Result<T, EmulatorError>;
+
+#[derive(Debug)]
+pub enum EmulatorError {
+    UnknownOpcode { opcode: Opcode, pc: usize },
+    StackOverflow { pc: usize },
+    StackUnderflow { pc: usize },
+    MemoryOutOfBounds { addr: usize, pc: usize },
+    PcOutOfBounds { pc: usize },
+    RomTooLarge { size: usize, max: usize },
+    AsmError { line: usize, message: String },
+    InvalidSaveState(String),
+    InvalidMovie(String),
+    IOError(std::io::Error),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::UnknownOpcode { opcode, pc } => {
+                write!(f, "unknown opcode {:#06X} at {:#05X}", opcode.raw(), pc)
+            }
+            EmulatorError::StackOverflow { pc } => write!(f, "stack overflow at {:#05X}", pc),
+            EmulatorError::StackUnderflow { pc } => write!(f, "stack underflow at {:#05X}", pc),
+            EmulatorError::MemoryOutOfBounds { addr, pc } => {
+                write!(f, "access to {:#06X} out of bounds at {:#05X}", addr, pc)
+            }
+            EmulatorError::PcOutOfBounds { pc } => write!(f, "PC {:#06X} is out of bounds", pc),
+            EmulatorError::RomTooLarge { size, max } => {
+                write!(f, "ROM is {} bytes, over the limit of {}", size, max)
+            }
+            EmulatorError::AsmError { line, message } => write!(f, "line {}: {}", line, message),
+            EmulatorError::InvalidSaveState(reason) => write!(f, "invalid save state: {}", reason),
+            EmulatorError::InvalidMovie(reason) => write!(f, "invalid movie: {}", reason),
+            EmulatorError::IOError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
+
+impl From<std::io::Error> for EmulatorError {
+    fn from(e: std::io::Error) -> Self {
+        EmulatorError::IOError(e)
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{
+    cpu::{Cpu, NUM_KEYS},
+    display::Display,
+    quirks::Quirks,
+    state::{self, StateReader, StateWriter},
+    Result,
+};
+
+/// The rate at which the delay and sound timers tick, and so the length of a frame.
+pub const FRAME_RATE: u32 = 60;
+
+pub const DEFAULT_CYCLES_PER_FRAME: usize = 10;
+
+/// A headless CHIP-8 machine, ready to be driven by any frontend.
+///
+/// Time is measured in cycles, each executing one instruction: every
+/// `cycles_per_frame` cycles make up one frame, at the start of which the
+/// timers tick once. Ticking first means that after a frame the sound timer
+/// still says whether the buzzer was on during it. Under the display wait
+/// quirk, drawing a sprite stalls the CPU, so the rest of the frame's cycles
+/// pass without executing anything.
+#[derive(Clone, Debug)]
+pub struct Machine {
+    cpu: Cpu,
+    cycles_per_frame: usize,
+    frame_cycles: usize,
+    // Whether a draw has stalled the CPU until the frame ends
+    stalled: bool,
+    frames: u64,
+    rom_hash: u64,
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Machine {
+            cpu: Cpu::new(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            frame_cycles: 0,
+            stalled: false,
+            frames: 0,
+            rom_hash: state::rom_hash(&[]),
+        }
+    }
+
+    /// Loads a ROM at 0x200, failing if it's too big for the memory above it.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result {
+        self.cpu.load(rom)?;
+
+        self.rom_hash = state::rom_hash(rom);
+
+        Ok(())
+    }
+
+    /// The hash of the loaded ROM, which save states are tied to.
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// Snapshots everything about the running program into a versioned binary state.
+    ///
+    /// Quirks and speed aren't included, as they're settings rather than state.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.rom_hash);
+
+        self.cpu.save_state(&mut writer);
+
+        writer.usize(self.frame_cycles);
+
+        writer.bool(self.stalled);
+
+        writer.u64(self.frames);
+
+        writer.finish()
+    }
+
+    /// Restores a state from [`save_state`](Machine::save_state), leaving the
+    /// machine untouched if it's invalid or was saved from a different ROM.
+    pub fn load_state(&mut self, state: &[u8]) -> Result {
+        let mut reader = StateReader::new(state, self.rom_hash)?;
+
+        let mut cpu = self.cpu.clone();
+
+        cpu.load_state(&mut reader)?;
+
+        let frame_cycles = reader.usize()?;
+
+        let stalled = reader.bool()?;
+
+        let frames = reader.u64()?;
+
+        reader.finish()?;
+
+        self.cpu = cpu;
+
+        self.frame_cycles = frame_cycles.min(self.cycles_per_frame - 1);
+
+        self.stalled = stalled;
+
+        self.frames = frames;
+
+        Ok(())
+    }
+
+    pub fn cycles_per_frame(&self) -> usize {
+        self.cycles_per_frame
+    }
+
+    pub fn set_cycles_per_frame(&mut self, cycles: usize) {
+        self.cycles_per_frame = cycles.max(1);
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.cpu.memory_size()
+    }
+
+    /// Sets the size of the address space, so the ROM and the program are
+    /// held to the platform's memory.
+    pub fn set_memory_size(&mut self, size: usize) {
+        self.cpu.set_memory_size(size);
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.cpu.quirks()
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+    }
+
+    /// Whether a draw under the display wait quirk has stalled the CPU for the
+    /// rest of the frame.
+    pub fn stalled(&self) -> bool {
+        self.stalled
+    }
+
+    /// The number of frames completed so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Runs a single cycle, ticking the timers first if it starts a frame.
+    ///
+    /// The cycle executes an instruction unless the CPU is
+    /// [stalled](Machine::stalled). A faulting instruction still uses up its cycle.
+    pub fn step(&mut self) -> Result {
+        if self.frame_cycles == 0 {
+            self.cpu.tick_timers();
+        }
+
+        let result = if self.stalled {
+            Ok(())
+        } else {
+            self.cpu.step()
+        };
+
+        // A draw with the display wait quirk stalls until the next frame,
+        // idling through the rest of this one's cycles
+        self.stalled |= self.cpu.take_display_wait();
+
+        self.frame_cycles += 1;
+
+        if self.frame_cycles >= self.cycles_per_frame {
+            self.end_frame();
+        }
+
+        result
+    }
+
+    /// Runs `cycles` cycles back to back, stopping at the first fault.
+    pub fn run(&mut self, cycles: usize) -> Result {
+        for _ in 0..cycles {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    /// Executes instructions up to the end of the current frame, stopping at
+    /// the first fault. Running again after a fault finishes the same frame.
+    pub fn run_frame(&mut self) -> Result {
+        let frame = self.frames;
+
+        while self.frames == frame {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn display(&self) -> &Display {
+        self.cpu.display()
+    }
+
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        self.cpu.set_key(key, pressed);
+    }
+
+    pub fn set_keys(&mut self, keys: &[bool; NUM_KEYS]) {
+        for (key, pressed) in keys.iter().enumerate() {
+            self.cpu.set_key(key, *pressed);
+        }
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    fn end_frame(&mut self) {
+        self.frame_cycles = 0;
+
+        self.stalled = false;
+
+        // Presses and releases are only kept for the frame they happen in
+        self.cpu.clear_key_events();
+
+        self.frames += 1;
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Machine::new()
+    }
+}
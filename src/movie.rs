@@ -0,0 +1,259 @@
+use crate::{
+    cpu::NUM_KEYS,
+    machine::Machine,
+    quirks::{Platform, Quirks},
+    rng::{Rng, RngAlgorithm},
+    EmulatorError, Result,
+};
+
+const MAGIC: &[u8; 4] = b"MPMV";
+
+/// The movie format version, bumped whenever the layout changes.
+pub const MOVIE_VERSION: u16 = 2;
+
+// Magic, version, ROM hash, seed, RNG, quirks, platform and cycles per frame
+const HEADER_SIZE: usize = 4 + 2 + 8 + 8 + 1 + 1 + 1 + 4;
+
+// Each run is a frame count followed by the keys held for those frames
+const RUN_SIZE: usize = 4 + 2;
+
+// A day of frames, far longer than any real recording, so that a corrupt count
+// can't ask for gigabytes of memory
+const MAX_FRAMES: usize = 24 * 60 * 60 * 60;
+
+fn invalid(message: impl Into<String>) -> EmulatorError {
+    EmulatorError::InvalidMovie(message.into())
+}
+
+/// A recording of the keys held on every frame since power on, along with
+/// everything else needed to replay it exactly: the ROM, the RNG seed, the
+/// quirks, the platform's memory and the number of instructions per frame.
+///
+/// Keys are stored as one 16 bit mask per frame and run-length encoded on disk,
+/// as they rarely change from one frame to the next.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    rom_hash: u64,
+    rng: Rng,
+    quirks: Quirks,
+    platform: Platform,
+    cycles_per_frame: usize,
+    frames: Vec<u16>,
+}
+
+impl Movie {
+    /// Starts an empty movie for a machine which has just been set up for `platform`.
+    pub fn new(machine: &Machine, platform: Platform) -> Self {
+        Movie {
+            rom_hash: machine.rom_hash(),
+            rng: Rng::new(machine.cpu().rng().seed(), machine.cpu().rng().algorithm()),
+            quirks: machine.quirks(),
+            platform,
+            cycles_per_frame: machine.cycles_per_frame(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn cycles_per_frame(&self) -> usize {
+        self.cycles_per_frame
+    }
+
+    /// The number of recorded frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Records the keys held during `frame`, discarding anything recorded after it
+    /// so that loading a state or rewinding mid-recording rewrites what follows.
+    pub fn record(&mut self, frame: u64, keys: &[bool; NUM_KEYS]) {
+        let frame = frame as usize;
+
+        // Frames skipped over keep holding the last keys
+        let last = self.frames.last().copied().unwrap_or(0);
+
+        self.frames.resize(frame, last);
+
+        let mask = keys
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (key, pressed)| mask | (*pressed as u16) << key);
+
+        self.frames.push(mask);
+    }
+
+    /// The keys held during `frame`, or `None` once the movie has run out.
+    pub fn keys(&self, frame: u64) -> Option<[bool; NUM_KEYS]> {
+        let mask = *self.frames.get(frame as usize)?;
+
+        let mut keys = [false; NUM_KEYS];
+
+        for (key, pressed) in keys.iter_mut().enumerate() {
+            *pressed = mask & (1 << key) != 0;
+        }
+
+        Some(keys)
+    }
+
+    /// Sets up a machine with the ROM already loaded to replay the movie from
+    /// the start, failing if the movie was recorded with a different ROM.
+    pub fn apply(&self, machine: &mut Machine) -> Result {
+        if machine.rom_hash() != self.rom_hash {
+            return Err(invalid("recorded with a different ROM"));
+        }
+
+        machine.set_quirks(self.quirks);
+
+        machine.set_memory_size(self.platform.memory_size());
+
+        machine.set_cycles_per_frame(self.cycles_per_frame);
+
+        machine.cpu_mut().set_rng(self.rng);
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+
+        bytes.extend_from_slice(MAGIC);
+
+        bytes.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+
+        bytes.extend_from_slice(&self.rom_hash.to_le_bytes());
+
+        bytes.extend_from_slice(&self.rng.seed().to_le_bytes());
+
+        bytes.push(self.rng.algorithm().id());
+
+        bytes.push(quirk_bits(self.quirks));
+
+        bytes.push(self.platform.id());
+
+        bytes.extend_from_slice(&(self.cycles_per_frame as u32).to_le_bytes());
+
+        let mut frames = self.frames.iter().peekable();
+
+        while let Some(&mask) = frames.next() {
+            let mut count: u32 = 1;
+
+            while frames.next_if_eq(&&mask).is_some() {
+                count += 1;
+            }
+
+            bytes.extend_from_slice(&count.to_le_bytes());
+
+            bytes.extend_from_slice(&mask.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+            return Err(invalid("not a movie"));
+        }
+
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        let u64_at = |i: usize| u32_at(i) as u64 | (u32_at(i + 4) as u64) << 32;
+
+        let version = u16_at(4);
+
+        if version != MOVIE_VERSION {
+            return Err(invalid(format!(
+                "version {} isn't supported, expected {}",
+                version, MOVIE_VERSION
+            )));
+        }
+
+        let algorithm = RngAlgorithm::from_id(bytes[22])
+            .ok_or_else(|| invalid(format!("unknown RNG {}", bytes[22])))?;
+
+        let platform = Platform::from_id(bytes[24])
+            .ok_or_else(|| invalid(format!("unknown platform {}", bytes[24])))?;
+
+        let runs = &bytes[HEADER_SIZE..];
+
+        if !runs.len().is_multiple_of(RUN_SIZE) {
+            return Err(invalid("truncated"));
+        }
+
+        let mut frames = Vec::new();
+
+        let mut total: usize = 0;
+
+        for run in runs.chunks(RUN_SIZE) {
+            let count = u32::from_le_bytes([run[0], run[1], run[2], run[3]]) as usize;
+
+            let mask = u16::from_le_bytes([run[4], run[5]]);
+
+            total = total
+                .checked_add(count)
+                .filter(|&total| total <= MAX_FRAMES)
+                .ok_or_else(|| invalid(format!("over the limit of {} frames", MAX_FRAMES)))?;
+
+            frames.extend(std::iter::repeat_n(mask, count));
+        }
+
+        Ok(Movie {
+            rom_hash: u64_at(6),
+            rng: Rng::new(u64_at(14), algorithm),
+            quirks: quirks_from_bits(bytes[23]),
+            platform,
+            cycles_per_frame: (u32_at(25) as usize).max(1),
+            frames,
+        })
+    }
+}
+
+fn quirk_bits(quirks: Quirks) -> u8 {
+    [
+        quirks.shift,
+        quirks.load_store,
+        quirks.jump,
+        quirks.vf_reset,
+        quirks.clipping,
+        quirks.display_wait,
+        quirks.key_release,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (i, set)| bits | (*set as u8) << i)
+}
+
+fn quirks_from_bits(bits: u8) -> Quirks {
+    let set = |i: u8| bits & (1 << i) != 0;
+
+    Quirks {
+        shift: set(0),
+        load_store: set(1),
+        jump: set(2),
+        vf_reset: set(3),
+        clipping: set(4),
+        display_wait: set(5),
+        key_release: set(6),
+    }
+}
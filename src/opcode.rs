@@ -1,8 +1,8 @@
-use crate::{instr::Instr, EmulatorError};
+use crate::instr::Instr;
 use std::convert::TryInto;
 
 #[derive(Copy, Clone, Debug)]
-pub(crate) struct Opcode {
+pub struct Opcode {
     pub(crate) opcode: u16,
     raw: u16,
     nnn: usize,
@@ -10,10 +10,11 @@ pub(crate) struct Opcode {
     y: usize,
     n: u8,
     kk: u8,
+    operand: usize,
 }
 
 impl Opcode {
-    pub(crate) fn new(opcode: u16) -> Self {
+    pub fn new(opcode: u16) -> Self {
         Opcode {
             opcode: ((opcode & 0xF000) >> 12),
             raw: opcode,
@@ -22,18 +23,39 @@ impl Opcode {
             y: ((opcode & 0x00F0) >> 4) as usize,
             n: (opcode & 0x000F) as u8,
             kk: (opcode & 0x00FF) as u8,
+            operand: 0,
         }
     }
+
+    // The word following the opcode, which F000 NNNN uses as its address
+    pub fn with_operand(self, operand: u16) -> Self {
+        Opcode {
+            operand: operand as usize,
+            ..self
+        }
+    }
+
+    pub fn raw(&self) -> u16 {
+        self.raw
+    }
 }
 
+// An opcode that isn't an instruction comes back as is, for the caller to say where it was
 impl TryInto<Instr> for Opcode {
-    type Error = EmulatorError;
+    type Error = Opcode;
 
     fn try_into(self) -> Result<Instr, Self::Error> {
         match self.opcode {
-            0x0 => match self.kk {
-                0xE0 => Ok(Instr::Clear),
-                0xEE => Ok(Instr::Return),
+            0x0 => match self.nnn {
+                0x0E0 => Ok(Instr::Clear),
+                0x0EE => Ok(Instr::Return),
+                0x0C0..=0x0CF => Ok(Instr::ScrollDown { rows: self.n }),
+                0x0D0..=0x0DF => Ok(Instr::ScrollUp { rows: self.n }),
+                0x0FB => Ok(Instr::ScrollRight),
+                0x0FC => Ok(Instr::ScrollLeft),
+                0x0FD => Ok(Instr::Exit),
+                0x0FE => Ok(Instr::LoRes),
+                0x0FF => Ok(Instr::HiRes),
 
                 // This isn't used, but is is necessary (should this be the default case?)
                 _ => Ok(Instr::JumpToMachineCode { addr: self.nnn }),
@@ -48,10 +70,21 @@ impl TryInto<Instr> for Opcode {
                 reg: self.x,
                 lit: self.kk,
             }),
-            0x5 => Ok(Instr::SkipNextEqualRegister {
-                left: self.x,
-                right: self.y,
-            }),
+            0x5 => match self.n {
+                0x0 => Ok(Instr::SkipNextEqualRegister {
+                    left: self.x,
+                    right: self.y,
+                }),
+                0x2 => Ok(Instr::StoreRegisterRange {
+                    first: self.x,
+                    last: self.y,
+                }),
+                0x3 => Ok(Instr::ReadRegisterRange {
+                    first: self.x,
+                    last: self.y,
+                }),
+                _ => Err(self),
+            },
             0x6 => Ok(Instr::RegisterSetLiteral {
                 reg: self.x,
                 lit: self.kk,
@@ -93,8 +126,11 @@ impl TryInto<Instr> for Opcode {
                     left: self.x,
                     right: self.y,
                 }),
-                0xE => Ok(Instr::RegisterSetRegisterShl { reg: self.x }),
-                _ => Err(EmulatorError::UnknownOpcode(self)),
+                0xE => Ok(Instr::RegisterSetRegisterShl {
+                    left: self.x,
+                    right: self.y,
+                }),
+                _ => Err(self),
             },
             0x9 => Ok(Instr::SkipNextNotEqualRegister {
                 left: self.x,
@@ -106,31 +142,46 @@ impl TryInto<Instr> for Opcode {
                 reg: self.x,
                 lit: self.kk,
             }),
-            0xD => Ok(Instr::DrawSprite {
-                x: self.x,
-                y: self.y,
-                size: self.n,
-            }),
+            0xD => match self.n {
+                0x0 => Ok(Instr::DrawLargeSprite {
+                    x: self.x,
+                    y: self.y,
+                }),
+                _ => Ok(Instr::DrawSprite {
+                    x: self.x,
+                    y: self.y,
+                    size: self.n,
+                }),
+            },
             0xE => match self.kk {
                 0x9E => Ok(Instr::SkipNextKeyPressed { reg: self.x }),
                 0xA1 => Ok(Instr::SkipNextKeyNotPressed { reg: self.x }),
-                _ => Err(EmulatorError::UnknownOpcode(self)),
+                _ => Err(self),
             },
             0xF => match self.kk {
-                0x07 => Ok(Instr::SetDelayTimerValue {
-                    reg: self.x,
+                0x00 if self.x == 0 => Ok(Instr::SetIndexLong {
+                    value: self.operand,
+                }),
+                0x01 => Ok(Instr::SelectPlanes {
+                    planes: self.x as u8,
                 }),
+                0x02 if self.x == 0 => Ok(Instr::LoadAudioPattern),
+                0x07 => Ok(Instr::SetDelayTimerValue { reg: self.x }),
                 0x0A => Ok(Instr::KeyPressWait { reg: self.x }),
                 0x15 => Ok(Instr::SetDelayTimerRegister { reg: self.x }),
                 0x18 => Ok(Instr::SetSoundTimerRegister { reg: self.x }),
                 0x1E => Ok(Instr::IndexAddAssignRegister { reg: self.x }),
                 0x29 => Ok(Instr::SetIndexToDigitSprite { reg: self.x }),
+                0x30 => Ok(Instr::SetIndexToBigDigitSprite { reg: self.x }),
+                0x3A => Ok(Instr::SetPitchRegister { reg: self.x }),
                 0x33 => Ok(Instr::StoreBCDAtIndex { reg: self.x }),
                 0x55 => Ok(Instr::StoreRegistersAtIndex { start_addr: self.x }),
                 0x65 => Ok(Instr::ReadRegistersAtIndex { start_addr: self.x }),
-                _ => Err(EmulatorError::UnknownOpcode(self)),
+                0x75 => Ok(Instr::StoreRegistersInFlags { last_reg: self.x }),
+                0x85 => Ok(Instr::ReadRegistersFromFlags { last_reg: self.x }),
+                _ => Err(self),
             },
-            _ => Err(EmulatorError::UnknownOpcode(self)),
+            _ => Err(self),
         }
     }
 }
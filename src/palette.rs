@@ -0,0 +1,205 @@
+use crate::display::NUM_PLANES;
+use std::{fmt, str::FromStr};
+
+/// The number of colours a pixel can be: off, plane 1, plane 2 or both planes.
+pub const NUM_COLOURS: usize = 1 << NUM_PLANES;
+
+/// The `0xRRGGBB` colours pixels are drawn in, indexed by the planes they're lit on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colours: [u32; NUM_COLOURS],
+}
+
+impl Palette {
+    /// Takes the background colour, then plane 1's, plane 2's and the colour of both.
+    pub const fn new(colours: [u32; NUM_COLOURS]) -> Self {
+        Palette { colours }
+    }
+
+    pub fn colours(&self) -> &[u32; NUM_COLOURS] {
+        &self.colours
+    }
+
+    /// The colour of a pixel from [`Display::pixels`](crate::Display::pixels).
+    pub fn colour(&self, pixel: u8) -> u32 {
+        self.colours[pixel as usize % NUM_COLOURS]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Theme::default().palette()
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let colours: Vec<_> = self
+            .colours
+            .iter()
+            .map(|colour| format!("#{:06X}", colour))
+            .collect();
+
+        f.write_str(&colours.join(","))
+    }
+}
+
+/// Parses comma separated hex colours like `#000000,#FFFFFF`. Two colours set the
+/// background and foreground, with both planes drawn in the foreground, while
+/// four give every combination of planes its own colour.
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colours = s
+            .split(',')
+            .map(|colour| {
+                let hex = colour
+                    .trim()
+                    .trim_start_matches('#')
+                    .trim_start_matches("0x");
+
+                // from_str_radix would also take a sign
+                match u32::from_str_radix(hex, 16) {
+                    Ok(colour) if hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                        Ok(colour)
+                    }
+                    _ => Err(format!("'{}' isn't a colour like #RRGGBB", colour)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match *colours.as_slice() {
+            [background, foreground] => Ok(Palette::new([
+                background, foreground, foreground, foreground,
+            ])),
+            [background, plane_1, plane_2, both] => {
+                Ok(Palette::new([background, plane_1, plane_2, both]))
+            }
+            _ => Err(format!("expected 2 or 4 colours, found {}", colours.len())),
+        }
+    }
+}
+
+/// The built in palettes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+    /// White on black.
+    #[default]
+    Classic,
+    /// A green phosphor monitor.
+    Green,
+    /// An amber phosphor monitor.
+    Amber,
+    /// An unlit LCD, in the greens of an early handheld.
+    Lcd,
+    /// Octo's yellow on brown.
+    Octo,
+    /// Saturated colours for telling the planes apart easily.
+    HighContrast,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 6] = [
+        Theme::Classic,
+        Theme::Green,
+        Theme::Amber,
+        Theme::Lcd,
+        Theme::Octo,
+        Theme::HighContrast,
+    ];
+
+    pub fn palette(self) -> Palette {
+        Palette::new(match self {
+            Theme::Classic => [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+            Theme::Green => [0x0A140A, 0x33FF66, 0x1A8033, 0xB3FFC6],
+            Theme::Amber => [0x140C00, 0xFFB000, 0x805800, 0xFFE0A0],
+            Theme::Lcd => [0x9BBC0F, 0x0F380F, 0x8BAC0F, 0x306230],
+            Theme::Octo => [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+            Theme::HighContrast => [0x000000, 0xFFFFFF, 0xFFFF00, 0x00FFFF],
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Classic => "classic",
+            Theme::Green => "green",
+            Theme::Amber => "amber",
+            Theme::Lcd => "lcd",
+            Theme::Octo => "octo",
+            Theme::HighContrast => "high-contrast",
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Theme::ALL
+            .iter()
+            .copied()
+            .find(|theme| theme.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Theme::ALL.iter().map(|t| t.name()).collect();
+
+                format!(
+                    "unknown theme '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_colours_draw_every_plane_in_the_foreground() {
+        let palette: Palette = "#102030, 0xA0B0C0".parse().unwrap();
+
+        assert_eq!(palette.colours(), &[0x102030, 0xA0B0C0, 0xA0B0C0, 0xA0B0C0]);
+    }
+
+    #[test]
+    fn four_colours_give_each_plane_its_own() {
+        let palette: Palette = "000000,ff0000,00ff00,0000FF".parse().unwrap();
+
+        assert_eq!(palette.colours(), &[0x000000, 0xFF0000, 0x00FF00, 0x0000FF]);
+
+        // Displayed palettes parse back to themselves
+        assert_eq!(palette.to_string().parse(), Ok(palette));
+    }
+
+    #[test]
+    fn only_two_or_four_colours_are_allowed() {
+        for colours in ["#000000", "#000000,#111111,#222222", ""] {
+            assert!(colours.parse::<Palette>().is_err(), "{}", colours);
+        }
+
+        assert_eq!(
+            "#000000,#111111,#222222".parse::<Palette>(),
+            Err("expected 2 or 4 colours, found 3".to_string())
+        );
+    }
+
+    #[test]
+    fn colours_must_be_six_hex_digits() {
+        for colour in ["#FFF", "#1234567", "#GGGGGG", "#+12345", "#"] {
+            let colours = format!("#000000,{}", colour);
+
+            assert_eq!(
+                colours.parse::<Palette>(),
+                Err(format!("'{}' isn't a colour like #RRGGBB", colour)),
+            );
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::cpu::{INSTR_START, MEM_SIZE};
+use std::{fmt, str::FromStr};
+
+/// Switches between the differing interpretations of the ambiguous opcodes.
+///
+/// Each flag enables the behaviour it is named after, following Octo's naming.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VX in place and ignore VY.
+    pub shift: bool,
+
+    /// FX55/FX65 leave the index register untouched.
+    pub load_store: bool,
+
+    /// BNNN jumps to NNN plus VX (where X is the top nibble of NNN) rather than V0.
+    pub jump: bool,
+
+    /// 8XY1/8XY2/8XY3 reset VF to zero.
+    pub vf_reset: bool,
+
+    /// Sprites are clipped at the screen edges rather than wrapping around.
+    pub clipping: bool,
+
+    /// DXYN idles the CPU through the rest of the frame once it has drawn, as
+    /// the VIP waited for the vertical blank, capping sprite draws at one a frame.
+    pub display_wait: bool,
+
+    /// FX0A waits for a key to be pressed and then released, rather than
+    /// taking whichever key is held.
+    pub key_release: bool,
+}
+
+/// The named quirk presets, one per historical interpreter.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    #[default]
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 4] = [
+        Platform::CosmacVip,
+        Platform::Chip48,
+        Platform::SuperChip,
+        Platform::XoChip,
+    ];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::CosmacVip => Quirks {
+                shift: false,
+                load_store: false,
+                jump: false,
+                vf_reset: true,
+                clipping: true,
+                display_wait: true,
+                key_release: true,
+            },
+            Platform::Chip48 | Platform::SuperChip => Quirks {
+                shift: true,
+                load_store: true,
+                jump: true,
+                vf_reset: false,
+                clipping: true,
+                display_wait: false,
+                key_release: true,
+            },
+            Platform::XoChip => Quirks {
+                shift: false,
+                load_store: false,
+                jump: false,
+                vf_reset: false,
+                clipping: false,
+                display_wait: false,
+                key_release: true,
+            },
+        }
+    }
+
+    /// The size of the platform's address space: 4KiB, or 64KiB for XO-CHIP.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::XoChip => MEM_SIZE,
+            _ => 0x1000,
+        }
+    }
+
+    /// The largest ROM that fits in memory above the interpreter's reserved first 512 bytes.
+    pub fn max_rom_size(self) -> usize {
+        self.memory_size() - INSTR_START
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Platform::CosmacVip => 0,
+            Platform::Chip48 => 1,
+            Platform::SuperChip => 2,
+            Platform::XoChip => 3,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        Platform::ALL
+            .iter()
+            .copied()
+            .find(|platform| platform.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::CosmacVip => "vip",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Platform::default().quirks()
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::ALL
+            .iter()
+            .copied()
+            .find(|platform| platform.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Platform::ALL.iter().map(|p| p.name()).collect();
+
+                format!(
+                    "unknown platform '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
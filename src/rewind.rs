@@ -0,0 +1,176 @@
+use crate::machine::{Machine, FRAME_RATE};
+use std::collections::VecDeque;
+
+/// Thirty seconds of snapshots at one per frame.
+pub const DEFAULT_REWIND_FRAMES: usize = 30 * FRAME_RATE as usize;
+
+/// A bounded history of [`Machine`] snapshots to step gameplay backwards through.
+///
+/// Only the newest snapshot is kept whole. Each older one is stored as the
+/// difference from the snapshot after it, XORed and then run-length encoded,
+/// so frames where little of memory or the display changed cost a few bytes.
+#[derive(Clone, Debug)]
+pub struct RewindBuffer {
+    capacity: usize,
+    latest: Option<Vec<u8>>,
+    // Oldest first, each turning the snapshot after it into the one before
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    /// Makes a buffer holding at most `capacity` snapshots.
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer {
+            capacity,
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of snapshots held, the newest of them being where rewinding starts from.
+    pub fn len(&self) -> usize {
+        match self.latest {
+            Some(_) => self.deltas.len() + 1,
+            None => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// The bytes used by the stored snapshots and deltas.
+    pub fn memory_usage(&self) -> usize {
+        let latest = self.latest.as_ref().map_or(0, Vec::len);
+
+        latest + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+
+        self.deltas.clear();
+    }
+
+    /// Records the machine's current state, dropping the oldest snapshot if full.
+    pub fn push(&mut self, machine: &Machine) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let state = machine.save_state();
+
+        match self.latest.take() {
+            Some(previous) if previous.len() == state.len() => {
+                self.deltas.push_back(encode_delta(&state, &previous));
+
+                if self.deltas.len() >= self.capacity {
+                    self.deltas.pop_front();
+                }
+            }
+            // Deltas only work between states of the same size
+            _ => self.deltas.clear(),
+        }
+
+        self.latest = Some(state);
+    }
+
+    /// Steps the machine back a snapshot. The newest snapshot is the state the
+    /// machine is already in, so this restores the one before it and drops the
+    /// newest, going further back each call. Returns false once there's nothing
+    /// older left.
+    pub fn rewind(&mut self, machine: &mut Machine) -> bool {
+        let (mut state, delta) = match (self.latest.take(), self.deltas.pop_back()) {
+            (Some(state), Some(delta)) => (state, delta),
+            (latest, _) => {
+                self.latest = latest;
+
+                return false;
+            }
+        };
+
+        apply_delta(&mut state, &delta);
+
+        // Snapshots come from this machine, so only a different ROM can make them fail
+        if machine.load_state(&state).is_err() {
+            self.clear();
+
+            return false;
+        }
+
+        self.latest = Some(state);
+
+        true
+    }
+}
+
+impl Default for RewindBuffer {
+    fn default() -> Self {
+        RewindBuffer::new(DEFAULT_REWIND_FRAMES)
+    }
+}
+
+// A delta is a series of runs, each a count of unchanged bytes followed by a
+// count of changed bytes and their XORed values
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+
+    let mut i = 0;
+
+    while i < from.len() {
+        let unchanged = (i..from.len())
+            .find(|&j| from[j] != to[j])
+            .unwrap_or(from.len());
+
+        let changed = (unchanged..from.len())
+            .find(|&j| from[j] == to[j])
+            .unwrap_or(from.len());
+
+        delta.extend_from_slice(&((unchanged - i) as u32).to_le_bytes());
+
+        delta.extend_from_slice(&((changed - unchanged) as u32).to_le_bytes());
+
+        delta.extend(
+            from[unchanged..changed]
+                .iter()
+                .zip(&to[unchanged..changed])
+                .map(|(a, b)| a ^ b),
+        );
+
+        i = changed;
+    }
+
+    delta
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let count =
+        |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+
+    let mut i = 0;
+
+    let mut pos = 0;
+
+    while pos < delta.len() {
+        i += count(&delta[pos..]);
+
+        let changed = count(&delta[pos + 4..]);
+
+        pos += 8;
+
+        for (byte, diff) in state[i..i + changed]
+            .iter_mut()
+            .zip(&delta[pos..pos + changed])
+        {
+            *byte ^= diff;
+        }
+
+        i += changed;
+
+        pos += changed;
+    }
+}
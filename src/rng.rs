@@ -0,0 +1,182 @@
+use crate::{
+    state::{self, StateReader, StateWriter},
+    Result,
+};
+use std::{fmt, str::FromStr};
+
+// The second page of the COSMAC VIP's CHIP-8 interpreter, 0x100 to 0x1FF, which
+// its generator reads bytes of its own code from
+#[rustfmt::skip]
+static VIP_CODE_PAGE: [u8; 256] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x45, 0xA3, 0x98, 0x56, 0xD4, 0xF8, 0x81, 0xBC, 0xF8, 0x95, 0xAC,
+    0x22, 0xDC, 0x12, 0x56, 0xD4, 0x06, 0xB8, 0xD4, 0x06, 0xA8, 0xD4, 0x64, 0x0A, 0x01, 0xE6, 0x8A,
+    0xF4, 0xAA, 0x3B, 0x28, 0x9A, 0xFC, 0x01, 0xBA, 0xD4, 0xF8, 0x81, 0xBA, 0x06, 0xFA, 0x0F, 0xAA,
+    0x0A, 0xAA, 0xD4, 0xE6, 0x06, 0xBF, 0x93, 0xBE, 0xF8, 0x1B, 0xAE, 0x2A, 0x1A, 0xF8, 0x00, 0x5A,
+    0x0E, 0xF5, 0x3B, 0x4B, 0x56, 0x0A, 0xFC, 0x01, 0x5A, 0x30, 0x40, 0x4E, 0xF6, 0x3B, 0x3C, 0x9F,
+    0x56, 0x2A, 0x2A, 0xD4, 0x00, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x07, 0x5A, 0x87, 0xF3, 0x17,
+    0x1A, 0x3A, 0x5B, 0x12, 0xD4, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x0A, 0x57, 0x87, 0xF3, 0x17,
+    0x1A, 0x3A, 0x6B, 0x12, 0xD4, 0x15, 0x85, 0x22, 0x73, 0x95, 0x52, 0x25, 0x45, 0xA5, 0x86, 0xFA,
+    0x0F, 0xB5, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x82, 0x15, 0x15, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x88,
+    0xD4, 0x45, 0x07, 0x30, 0x8C, 0x45, 0x07, 0x30, 0x84, 0xE6, 0x62, 0x26, 0x45, 0xA3, 0x36, 0x88,
+    0xD4, 0x3E, 0x88, 0xD4, 0xF8, 0xF0, 0xA7, 0xE7, 0x45, 0xF4, 0xA5, 0x86, 0xFA, 0x0F, 0x3B, 0xB2,
+    0xFC, 0x01, 0xB5, 0xD4, 0x45, 0x56, 0xD4, 0x45, 0xE6, 0xF4, 0x56, 0xD4, 0x45, 0xFA, 0x0F, 0x3A,
+    0xC4, 0x07, 0x56, 0xD4, 0xAF, 0x22, 0xF8, 0xD3, 0x73, 0x8F, 0xF9, 0xF0, 0x52, 0xE6, 0x07, 0xD2,
+    0x56, 0xF8, 0xFF, 0xA6, 0xF8, 0x00, 0x7E, 0x56, 0xD4, 0x19, 0x89, 0xAE, 0x93, 0xBE, 0x99, 0xEE,
+    0xF4, 0x56, 0x76, 0xE6, 0xF4, 0xB9, 0x56, 0x45, 0xF2, 0x56, 0xD4, 0x45, 0xAA, 0x86, 0xFA, 0x0F,
+    0xBA, 0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0, 0x00,
+];
+
+/// The generator behind CXKK.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RngAlgorithm {
+    /// A xorshift64* generator, with well spread output.
+    #[default]
+    Xorshift,
+    /// The COSMAC VIP interpreter's, which steps a pointer through a page of its
+    /// own code, adds the byte it finds to the last number, then adds that sum
+    /// rotated right by one through the carry.
+    Vip,
+}
+
+impl RngAlgorithm {
+    pub const ALL: [RngAlgorithm; 2] = [RngAlgorithm::Xorshift, RngAlgorithm::Vip];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RngAlgorithm::Xorshift => "xorshift",
+            RngAlgorithm::Vip => "vip",
+        }
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            RngAlgorithm::Xorshift => 0,
+            RngAlgorithm::Vip => 1,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        RngAlgorithm::ALL
+            .iter()
+            .copied()
+            .find(|algorithm| algorithm.id() == id)
+    }
+}
+
+impl fmt::Display for RngAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RngAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        RngAlgorithm::ALL
+            .iter()
+            .copied()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown RNG '{}', expected xorshift or vip", s))
+    }
+}
+
+/// A seedable random number generator, so runs can be replayed exactly.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    algorithm: RngAlgorithm,
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64, algorithm: RngAlgorithm) -> Self {
+        let state = match algorithm {
+            // Scramble the seed, as xorshift gets stuck on zero and starts slowly from small seeds
+            RngAlgorithm::Xorshift => splitmix64(seed).max(1),
+            // The pointer starts in the low byte and the last number in the next
+            RngAlgorithm::Vip => seed & 0xFFFF,
+        };
+
+        Rng {
+            algorithm,
+            seed,
+            state,
+        }
+    }
+
+    pub fn algorithm(&self) -> RngAlgorithm {
+        self.algorithm
+    }
+
+    /// The seed the generator started from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Produces the next random byte.
+    pub(crate) fn next_byte(&mut self) -> u8 {
+        match self.algorithm {
+            RngAlgorithm::Xorshift => {
+                self.state ^= self.state >> 12;
+
+                self.state ^= self.state << 25;
+
+                self.state ^= self.state >> 27;
+
+                (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+            }
+            RngAlgorithm::Vip => {
+                let pointer = (self.state as u8).wrapping_add(1);
+
+                let last = (self.state >> 8) as u8;
+
+                let (sum, carry) = last.overflowing_add(VIP_CODE_PAGE[pointer as usize]);
+
+                // Rotating right through the carry brings it in at the top
+                let total = (sum >> 1 | (carry as u8) << 7).wrapping_add(sum);
+
+                self.state = (total as u64) << 8 | pointer as u64;
+
+                total
+            }
+        }
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.u8(self.algorithm.id());
+
+        writer.u64(self.seed);
+
+        writer.u64(self.state);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result {
+        let id = reader.u8()?;
+
+        self.algorithm = RngAlgorithm::from_id(id)
+            .ok_or_else(|| state::invalid(format!("unknown RNG {}", id)))?;
+
+        self.seed = reader.u64()?;
+
+        self.state = reader.u64()?;
+
+        Ok(())
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new(0, RngAlgorithm::default())
+    }
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    z ^ (z >> 31)
+}
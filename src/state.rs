@@ -0,0 +1,174 @@
+use crate::{EmulatorError, Result};
+
+const MAGIC: &[u8; 4] = b"MPSS";
+
+/// The save state format version, bumped whenever the layout changes.
+pub const STATE_VERSION: u16 = 4;
+
+/// Hashes a ROM with 64 bit FNV-1a, so save states can tell which game they belong to.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+pub(crate) fn invalid(message: impl Into<String>) -> EmulatorError {
+    EmulatorError::InvalidSaveState(message.into())
+}
+
+/// Appends little-endian fields to a save state.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Starts a state with the header for a machine running the ROM with hash `rom_hash`.
+    pub(crate) fn new(rom_hash: u64) -> Self {
+        let mut writer = StateWriter::default();
+
+        writer.bytes(MAGIC);
+
+        writer.u16(STATE_VERSION);
+
+        writer.u64(rom_hash);
+
+        writer
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u128(&mut self, value: u128) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    // Addresses are stored as 32 bits whatever the host's pointer size
+    pub(crate) fn usize(&mut self, value: usize) {
+        self.bytes(&(value as u32).to_le_bytes());
+    }
+}
+
+/// Reads back the fields written by a [`StateWriter`], in the same order.
+#[derive(Clone, Debug)]
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the header, refusing states from other versions or other ROMs.
+    pub(crate) fn new(bytes: &'a [u8], rom_hash: u64) -> Result<Self> {
+        let mut reader = StateReader { bytes };
+
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a save state"));
+        }
+
+        let version = reader.u16()?;
+
+        if version != STATE_VERSION {
+            return Err(invalid(format!(
+                "version {} isn't supported, expected {}",
+                version, STATE_VERSION
+            )));
+        }
+
+        if reader.u64()? != rom_hash {
+            return Err(invalid("saved from a different ROM"));
+        }
+
+        Ok(reader)
+    }
+
+    /// Fails if anything is left over, which means the state doesn't match the layout.
+    pub(crate) fn finish(self) -> Result {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("trailing data"))
+        }
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(invalid("truncated"));
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+
+        self.bytes = rest;
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn fill(&mut self, buffer: &mut [u8]) -> Result {
+        buffer.copy_from_slice(self.bytes(buffer.len())?);
+
+        Ok(())
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(invalid(format!("{} isn't a boolean", value))),
+        }
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16> {
+        let mut bytes = [0; 2];
+
+        self.fill(&mut bytes)?;
+
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+
+        self.fill(&mut bytes)?;
+
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u128(&mut self) -> Result<u128> {
+        let mut bytes = [0; 16];
+
+        self.fill(&mut bytes)?;
+
+        Ok(u128::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn usize(&mut self) -> Result<usize> {
+        let mut bytes = [0; 4];
+
+        self.fill(&mut bytes)?;
+
+        Ok(u32::from_le_bytes(bytes) as usize)
+    }
+}
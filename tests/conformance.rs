@@ -0,0 +1,1255 @@
+//! Runs small ROMs, assembled from the Octo source in each test, to completion and
+//! checks the display against golden images in `tests/golden` and the registers
+//! against what each quirk profile should leave in them. The same ROMs check
+//! that disassembly reassembles to the same bytes.
+//!
+//! Set `MUSHYPEAS_BLESS=1` to write the golden images from the current output
+//! instead of comparing against them.
+
+use mushypeas::{
+    Beeper, EmulatorError, Machine, MemorySink, Movie, Platform, RewindBuffer, Rng, RngAlgorithm,
+    Syntax, DEFAULT_SAMPLE_RATE, DEFAULT_VOLUME, FRAME_RATE, MEM_SIZE, NUM_KEYS,
+};
+use std::{env, fs, path::PathBuf};
+
+// Long enough for every program here, even one draw per frame
+const MAX_FRAMES: usize = 600;
+
+// Draws v0 as two hex digits at (vA, vB) and moves vA along, clobbering v1 and vF
+const PRELUDE: &str = "
+jump start
+
+: show
+  v1 := v0
+  v1 >>= v1 v1 >>= v1 v1 >>= v1 v1 >>= v1
+  i := hex v1
+  sprite vA vB 5
+  vA += 5
+  v1 := 0xF
+  v1 &= v0
+  i := hex v1
+  sprite vA vB 5
+  vA += 6
+;
+
+: start
+";
+
+fn assemble(source: &str) -> Vec<u8> {
+    let source = format!("{}{}", PRELUDE, source);
+
+    mushypeas::assemble(&source).unwrap_or_else(|e| panic!("{}\n{}", e, source))
+}
+
+fn machine(source: &str, platform: Platform) -> Machine {
+    let mut machine = Machine::new();
+
+    machine.set_quirks(platform.quirks());
+
+    machine.set_memory_size(platform.memory_size());
+
+    machine
+        .cpu_mut()
+        .set_rng(Rng::new(1, RngAlgorithm::Xorshift));
+
+    machine.load_rom(&assemble(source)).unwrap();
+
+    machine
+}
+
+// Runs the machine until the program exits, failing on any fault
+fn finish(mut machine: Machine) -> Machine {
+    for _ in 0..MAX_FRAMES {
+        if machine.cpu().halted() {
+            return machine;
+        }
+
+        machine.run_frame().unwrap();
+    }
+
+    panic!("program didn't exit within {} frames", MAX_FRAMES);
+}
+
+fn run(source: &str, platform: Platform) -> Machine {
+    finish(machine(source, platform))
+}
+
+// Runs the machine until it faults
+fn fault(source: &str, platform: Platform) -> EmulatorError {
+    let mut machine = machine(source, platform);
+
+    for _ in 0..MAX_FRAMES {
+        if let Err(e) = machine.run_frame() {
+            return e;
+        }
+    }
+
+    panic!("program didn't fault within {} frames", MAX_FRAMES);
+}
+
+fn assert_golden(name: &str, machine: &Machine) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect::<PathBuf>()
+        .with_extension("txt");
+
+    let actual = machine.display().to_ascii();
+
+    if env::var_os("MUSHYPEAS_BLESS").is_some() {
+        fs::write(&path, &actual).unwrap();
+
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {}, run with MUSHYPEAS_BLESS=1", path.display(), e));
+
+    assert!(
+        actual == expected,
+        "{} doesn't match the golden image\nexpected:\n{}\nactual:\n{}",
+        name,
+        expected,
+        actual
+    );
+}
+
+fn register(machine: &Machine, reg: usize) -> u8 {
+    machine.cpu().registers()[reg]
+}
+
+const FONT_ROM: &str = "
+vA := 1
+vB := 1
+v2 := 0
+loop
+  v0 := v2
+  i := hex v0
+  sprite vA vB 5
+  vA += 5
+  v2 += 1
+  if v2 == 8 then vB := 8
+  if v2 == 8 then vA := 1
+  if v2 != 16 then
+again
+exit
+";
+
+#[test]
+fn font() {
+    let machine = run(FONT_ROM, Platform::CosmacVip);
+
+    assert_golden("font", &machine);
+}
+
+const BIG_FONT_ROM: &str = "
+hires
+vA := 1
+vB := 1
+v2 := 0
+loop
+  i := bighex v2
+  sprite vA vB 10
+  vA += 9
+  v2 += 1
+  if v2 != 10 then
+again
+vA := 1
+vB := 14
+i := square
+sprite vA vB 0
+exit
+
+: square
+  0xFF 0xFF 0x80 0x01 0x80 0x01 0x80 0x01 0x80 0x01 0x80 0x01 0x80 0x01 0x80 0x01
+  0x80 0x01 0x80 0x01 0x80 0x01 0x80 0x01 0x80 0x01 0x80 0x01 0x80 0x01 0xFF 0xFF
+";
+
+#[test]
+fn big_font_and_large_sprites() {
+    let machine = run(BIG_FONT_ROM, Platform::SuperChip);
+
+    assert_golden("big_font", &machine);
+}
+
+const LORES_ROM: &str = "
+hires
+v0 := 0
+i := hex v0
+sprite v0 v0 5
+lores
+v1 := 8
+sprite v1 v1 5
+exit
+";
+
+#[test]
+fn resolution_changes_clear_the_screen() {
+    let machine = run(LORES_ROM, Platform::SuperChip);
+
+    assert!(!machine.display().is_hires());
+
+    assert_golden("lores", &machine);
+}
+
+const COLLISIONS_ROM: &str = "
+i := block
+v2 := 20
+sprite v2 v2 4
+clear
+
+vA := 1
+vB := 1
+i := block
+v2 := 40
+v3 := 10
+sprite v2 v3 4
+v0 := vF
+show
+i := block
+sprite v2 v3 4
+v0 := vF
+show
+i := block
+v2 += 2
+sprite v2 v3 4
+v0 := vF
+show
+i := block
+v2 += 8
+sprite v2 v3 4
+v0 := vF
+show
+exit
+
+: block
+  0xF0 0xF0 0xF0 0xF0
+";
+
+#[test]
+fn clear_and_collisions() {
+    let machine = run(COLLISIONS_ROM, Platform::CosmacVip);
+
+    // The first draw lands on a clear screen, the second erases it, the third
+    // draws over nothing and the fourth misses the third
+    assert_eq!(register(&machine, 0), 0);
+
+    assert_golden("collisions", &machine);
+}
+
+const EDGES_ROM: &str = "
+i := block
+v0 := 60
+v1 := 29
+sprite v0 v1 8
+v0 := 70
+v1 := 37
+sprite v0 v1 2
+exit
+
+: block
+  0xFF 0xFF 0xFF 0xFF 0xFF 0xFF 0xFF 0xFF
+";
+
+#[test]
+fn sprites_clip_or_wrap_at_the_edges() {
+    for &platform in &Platform::ALL {
+        let machine = run(EDGES_ROM, platform);
+
+        assert_golden(&format!("edges_{}", platform), &machine);
+    }
+}
+
+const SCROLL_ROM: &str = "
+i := block
+v0 := 24
+v1 := 12
+sprite v0 v1 8
+scroll-down 3
+scroll-right
+scroll-right
+scroll-left
+scroll-up 1
+exit
+
+: block
+  0xFF 0x81 0x81 0x81 0x81 0x81 0x81 0xFF
+";
+
+#[test]
+fn scrolling() {
+    let machine = run(SCROLL_ROM, Platform::XoChip);
+
+    assert_golden("scroll", &machine);
+}
+
+const PLANES_ROM: &str = "
+v0 := 4
+v1 := 4
+plane 1
+i := block
+sprite v0 v1 8
+plane 2
+v0 := 8
+sprite v0 v1 8
+plane 3
+v0 := 24
+i := both
+sprite v0 v1 4
+exit
+
+: block
+  0xFF 0xFF 0xFF 0xFF 0xFF 0xFF 0xFF 0xFF
+: both
+  0xF0 0xF0 0xF0 0xF0
+  0x3C 0x3C 0x3C 0x3C
+";
+
+#[test]
+fn bit_planes() {
+    let machine = run(PLANES_ROM, Platform::XoChip);
+
+    assert_eq!(machine.display().planes(), 3);
+
+    assert_golden("planes", &machine);
+}
+
+// An operator and the values of its two registers, followed by the result and
+// VF without and then with the shift quirk
+type AluCase = (&'static str, u8, u8, [(u8, u8); 2]);
+
+const ALU_CASES: [AluCase; 12] = [
+    ("+=", 0x10, 0x20, [(0x30, 0), (0x30, 0)]),
+    ("+=", 0xFF, 0x01, [(0x00, 1), (0x00, 1)]),
+    ("+=", 0xF0, 0x20, [(0x10, 1), (0x10, 1)]),
+    ("-=", 0x05, 0x03, [(0x02, 1), (0x02, 1)]),
+    ("-=", 0x03, 0x05, [(0xFE, 0), (0xFE, 0)]),
+    ("-=", 0x05, 0x05, [(0x00, 1), (0x00, 1)]),
+    ("=-", 0x03, 0x05, [(0x02, 1), (0x02, 1)]),
+    ("=-", 0x05, 0x03, [(0xFE, 0), (0xFE, 0)]),
+    ("=-", 0x05, 0x05, [(0x00, 1), (0x00, 1)]),
+    (">>=", 0x04, 0x81, [(0x40, 1), (0x02, 0)]),
+    ("<<=", 0x40, 0x81, [(0x02, 1), (0x80, 0)]),
+    ("<<=", 0x81, 0x40, [(0x80, 0), (0x02, 1)]),
+];
+
+#[test]
+fn arithmetic_flags() {
+    for &platform in &Platform::ALL {
+        let shift = platform.quirks().shift as usize;
+
+        let mut source = String::from("hires\n");
+
+        for (i, (op, left, right, expected)) in ALU_CASES.iter().enumerate() {
+            let program = format!("v2 := {}\nv3 := {}\nv2 {} v3\n", left, right, op);
+
+            let machine = run(&format!("{}exit\n", program), platform);
+
+            let case = format!("{}: {:#04X} {} {:#04X}", platform, left, op, right);
+
+            assert_eq!(register(&machine, 2), expected[shift].0, "{}", case);
+
+            assert_eq!(register(&machine, 0xF), expected[shift].1, "{}", case);
+
+            // Show each result and flag on the screen too, five cases to a row
+            source.push_str(&format!(
+                "vA := {}\nvB := {}\n{}v4 := vF\nv0 := v2\nshow\nv0 := v4\nshow\n",
+                1 + i % 5 * 25,
+                1 + i / 5 * 7,
+                program
+            ));
+        }
+
+        source.push_str("exit\n");
+
+        assert_golden(&format!("alu_{}", platform), &run(&source, platform));
+    }
+}
+
+#[test]
+fn vf_as_an_operand() {
+    for &platform in &Platform::ALL {
+        // The flag overwrites the result when VF is the destination
+        let machine = run("vF := 0xFF\nv1 := 1\nvF += v1\nexit", platform);
+
+        assert_eq!(register(&machine, 0xF), 1, "{}", platform);
+
+        let machine = run("vF := 3\nv1 := 5\nvF -= v1\nexit", platform);
+
+        assert_eq!(register(&machine, 0xF), 0, "{}", platform);
+
+        let machine = run("vF := 6\nvF >>= vF\nexit", platform);
+
+        assert_eq!(register(&machine, 0xF), 0, "{}", platform);
+
+        // VF is read before it's overwritten when it's the source
+        let machine = run("v1 := 0xFF\nvF := 1\nv1 += vF\nexit", platform);
+
+        assert_eq!(register(&machine, 1), 0, "{}", platform);
+
+        assert_eq!(register(&machine, 0xF), 1, "{}", platform);
+
+        // 7XNN never touches VF
+        let machine = run("vF := 9\nv1 := 0xFF\nv1 += 2\nexit", platform);
+
+        assert_eq!(register(&machine, 1), 1, "{}", platform);
+
+        assert_eq!(register(&machine, 0xF), 9, "{}", platform);
+    }
+}
+
+#[test]
+fn logic_and_the_vf_reset_quirk() {
+    for &platform in &Platform::ALL {
+        let reset = platform.quirks().vf_reset;
+
+        for &(op, expected) in &[("|=", 0x0E), ("&=", 0x08), ("^=", 0x06)] {
+            let machine = run(
+                &format!("vF := 7\nv0 := 0x0C\nv1 := 0x0A\nv0 {} v1\nexit", op),
+                platform,
+            );
+
+            assert_eq!(register(&machine, 0), expected, "{} {}", platform, op);
+
+            assert_eq!(
+                register(&machine, 0xF),
+                if reset { 0 } else { 7 },
+                "{} {}",
+                platform,
+                op
+            );
+        }
+
+        let machine = run("v0 := 0x42\nv1 := v0\nexit", platform);
+
+        assert_eq!(register(&machine, 1), 0x42);
+    }
+}
+
+#[test]
+fn jump_with_offset_and_the_jump_quirk() {
+    for &platform in &Platform::ALL {
+        let machine = run(
+            "
+            v0 := 4
+            v2 := 8
+            jump0 base
+
+            : base
+              v3 := 1 exit
+              v3 := 2 exit
+              v3 := 3 exit
+            ",
+            platform,
+        );
+
+        // The base is in 0x2NN, so the quirk adds V2 rather than V0
+        let expected = if platform.quirks().jump { 3 } else { 2 };
+
+        assert_eq!(register(&machine, 3), expected, "{}", platform);
+    }
+}
+
+#[test]
+fn save_and_load_with_the_load_store_quirk() {
+    for &platform in &Platform::ALL {
+        let machine = run(
+            "
+            i := data
+            v0 := 1
+            v1 := 2
+            v2 := 3
+            save v2
+            i := data
+            v0 := 0
+            v1 := 0
+            v2 := 0
+            load v2
+            exit
+
+            : data
+              0 0 0 0
+            ",
+            platform,
+        );
+
+        assert_eq!(&machine.cpu().registers()[..3], &[1, 2, 3], "{}", platform);
+
+        let data = machine.cpu().memory()[machine.cpu().pc()..]
+            .windows(3)
+            .position(|bytes| bytes == [1, 2, 3])
+            .map(|i| machine.cpu().pc() + i)
+            .expect("save wrote the registers");
+
+        // Without the quirk, the index ends up past the registers read
+        let expected = if platform.quirks().load_store {
+            data
+        } else {
+            data + 3
+        };
+
+        assert_eq!(machine.cpu().index(), expected, "{}", platform);
+    }
+}
+
+#[test]
+fn register_ranges() {
+    let machine = run(
+        "
+        i := data
+        v1 := 1
+        v2 := 2
+        v3 := 3
+        save v1 - v3
+        load v3 - v1
+        v4 := v1
+        i := data
+        load v1 - v3
+        exit
+
+        : data
+          0 0 0
+        ",
+        Platform::XoChip,
+    );
+
+    // Loading in reverse swaps the first and last, and the index never moves
+    assert_eq!(register(&machine, 4), 3);
+
+    assert_eq!(&machine.cpu().registers()[1..4], &[1, 2, 3]);
+}
+
+#[test]
+fn calls_and_skips() {
+    let machine = run(
+        "
+        v0 := 0
+        outer
+        v1 := 5
+        v2 := 5
+        v3 := 6
+        if v1 == 5 then v0 += 1
+        if v1 == 6 then v0 += 0x10
+        if v1 != 6 then v0 += 1
+        if v1 != 5 then v0 += 0x10
+        if v1 == v2 then v0 += 1
+        if v1 == v3 then v0 += 0x10
+        if v1 != v3 then v0 += 1
+        if v1 != v2 then v0 += 0x10
+        jump done
+        v0 := 0xFF
+
+        : outer
+          inner
+          v0 += 1
+        ;
+        : inner
+          v0 += 1
+        ;
+
+        : done
+          exit
+        ",
+        Platform::CosmacVip,
+    );
+
+    // Two from the subroutines and one for each condition that held
+    assert_eq!(register(&machine, 0), 6);
+
+    assert!(machine.cpu().stack().is_empty());
+}
+
+#[test]
+fn stack_faults() {
+    let e = fault(": recurse recurse", Platform::CosmacVip);
+
+    assert!(matches!(e, EmulatorError::StackOverflow { .. }), "{}", e);
+
+    let e = fault(";", Platform::CosmacVip);
+
+    assert!(matches!(e, EmulatorError::StackUnderflow { .. }), "{}", e);
+}
+
+#[test]
+fn faults_leave_the_cpu_untouched() {
+    let mut machine = machine("v0 := 1\n0xFF 0xFF", Platform::CosmacVip);
+
+    let e = loop {
+        if let Err(e) = machine.run_frame() {
+            break e;
+        }
+    };
+
+    let (pc, ticks) = (machine.cpu().pc(), machine.cpu().ticks());
+
+    assert!(
+        matches!(e, EmulatorError::UnknownOpcode { pc: at, .. } if at == pc),
+        "{}",
+        e
+    );
+
+    assert!(machine.cpu_mut().step().is_err());
+
+    assert_eq!((machine.cpu().pc(), machine.cpu().ticks()), (pc, ticks));
+
+    assert_eq!(register(&machine, 0), 1);
+}
+
+#[test]
+fn machine_code_calls_are_ignored() {
+    let machine = run("0x01 0x23\nv0 := 1\nexit", Platform::CosmacVip);
+
+    assert_eq!(register(&machine, 0), 1);
+}
+
+#[test]
+fn timers_count_down_each_frame() {
+    let machine = run(
+        "
+        v0 := 10
+        delay := v0
+        v0 := 30
+        buzzer := v0
+        loop
+          v1 := delay
+          if v1 != 0 then
+        again
+        exit
+        ",
+        Platform::XoChip,
+    );
+
+    let frames = machine.frames();
+
+    assert!(frames >= 10, "the delay ran out after {} frames", frames);
+
+    // Both timers tick together, so the sound timer has lost the same ten
+    assert_eq!(machine.cpu().sound_timer(), 20);
+}
+
+#[test]
+fn the_sound_timer_beeps_until_it_runs_out() {
+    let mut machine = machine(
+        "
+        v0 := 3
+        buzzer := v0
+        loop again
+        ",
+        Platform::XoChip,
+    );
+
+    let mut beeper = Beeper::new(MemorySink::new(), DEFAULT_SAMPLE_RATE);
+
+    for _ in 0..FRAME_RATE {
+        machine.run_frame().unwrap();
+
+        beeper.frame(machine.cpu());
+    }
+
+    let samples = beeper.into_sink().samples().to_vec();
+
+    // A second of frames makes a second of samples
+    assert_eq!(samples.len(), DEFAULT_SAMPLE_RATE as usize);
+
+    // The timer is set during the first frame and ticks down at the start of the next three
+    let (beep, rest) = samples.split_at(3 * samples.len() / FRAME_RATE as usize);
+
+    assert!(beep.iter().all(|sample| sample.abs() == DEFAULT_VOLUME));
+
+    assert!(beep.contains(&DEFAULT_VOLUME) && beep.contains(&-DEFAULT_VOLUME));
+
+    assert!(rest.iter().all(|&sample| sample == 0.0));
+}
+
+#[test]
+fn draws_wait_for_the_next_frame_with_the_display_wait_quirk() {
+    let source = "
+        i := hex v0
+        sprite v0 v0 5
+        v1 := 1
+        sprite v0 v0 5
+        v2 := 1
+        exit
+        ";
+
+    let mut machine = machine(source, Platform::CosmacVip);
+
+    // Each draw gives up the rest of its frame
+    machine.run_frame().unwrap();
+
+    assert_eq!(&machine.cpu().registers()[1..3], &[0, 0]);
+
+    machine.run_frame().unwrap();
+
+    assert_eq!(&machine.cpu().registers()[1..3], &[1, 0]);
+
+    let machine = finish(machine);
+
+    assert_eq!(machine.frames(), 3);
+
+    // Without the quirk both draws and the exit fit in the first frame
+    let machine = run(source, Platform::XoChip);
+
+    assert_eq!(machine.frames(), 1);
+
+    assert_eq!(&machine.cpu().registers()[1..3], &[1, 1]);
+}
+
+#[test]
+fn draws_stall_without_speeding_up_frames() {
+    let source = "
+        v0 := 60
+        delay := v0
+        i := hex v0
+        : loop
+          sprite v1 v1 5
+          jump loop
+        ";
+
+    // However many draws there are, a frame still lasts its ten cycles
+    for &platform in &[Platform::CosmacVip, Platform::XoChip] {
+        let mut machine = machine(source, platform);
+
+        machine.run(100).unwrap();
+
+        assert_eq!(machine.frames(), 10, "{}", platform);
+
+        // The timer is set in the first frame and ticks at the start of the other nine
+        assert_eq!(machine.cpu().delay_timer(), 51, "{}", platform);
+    }
+}
+
+#[test]
+fn keys() {
+    let mut machine = machine(
+        "
+        v0 := 5
+        v4 := 6
+        if v0 key then v1 := 1
+        if v0 -key then v2 := 1
+        if v4 key then v5 := 1
+        if v4 -key then v6 := 1
+        v3 := key
+        exit
+        ",
+        Platform::XoChip,
+    );
+
+    machine.set_key(5, true);
+
+    // FX0A has seen the press, but waits for the key to come back up
+    for _ in 0..3 {
+        machine.run_frame().unwrap();
+    }
+
+    assert_eq!(machine.cpu().key_wait(), Some(5));
+
+    machine.set_key(5, false);
+
+    let machine = finish(machine);
+
+    assert_eq!(&machine.cpu().registers()[1..7], &[1, 0, 5, 6, 0, 1]);
+}
+
+#[test]
+fn key_waits_take_each_press_once() {
+    let mut machine = machine(
+        "
+        v0 := 30
+        buzzer := v0
+        v0 := 2
+        delay := v0
+        loop
+          v0 := delay
+          if v0 != 0 then
+        again
+        v1 := key
+        v2 := key
+        exit
+        ",
+        Platform::CosmacVip,
+    );
+
+    let frames = |machine: &mut Machine, count| {
+        for _ in 0..count {
+            machine.run_frame().unwrap();
+        }
+    };
+
+    // A key already held when FX0A starts waiting isn't a press, even once released
+    machine.set_key(2, true);
+
+    frames(&mut machine, 5);
+
+    machine.set_key(2, false);
+
+    frames(&mut machine, 1);
+
+    assert_eq!(machine.cpu().key_wait(), None);
+
+    machine.set_key(4, true);
+
+    frames(&mut machine, 2);
+
+    assert_eq!(machine.cpu().key_wait(), Some(4));
+
+    assert_eq!(register(&machine, 1), 0);
+
+    // The timers carry on while FX0A blocks, ticking at the start of each of
+    // the 7 frames after the one that set the buzzer
+    assert_eq!(machine.cpu().sound_timer(), 30 - 7);
+
+    machine.set_key(4, false);
+
+    frames(&mut machine, 3);
+
+    assert_eq!(register(&machine, 1), 4);
+
+    assert_eq!(machine.cpu().key_wait(), None);
+
+    // A press and release within one frame still counts
+    machine.set_key(9, true);
+
+    machine.set_key(9, false);
+
+    let machine = finish(machine);
+
+    assert_eq!(register(&machine, 2), 9);
+}
+
+#[test]
+fn key_waits_without_the_key_release_quirk() {
+    let mut machine = machine("v0 := key\nv1 := key\nexit", Platform::CosmacVip);
+
+    let mut quirks = machine.quirks();
+
+    quirks.key_release = false;
+
+    machine.set_quirks(quirks);
+
+    machine.set_key(3, true);
+
+    // Whichever key is held is taken straight away, as often as it's asked for
+    let machine = finish(machine);
+
+    assert_eq!(&machine.cpu().registers()[..2], &[3, 3]);
+}
+
+#[test]
+fn random_numbers_are_masked_and_seeded() {
+    let source = "v0 := random 0x0F\nv1 := random 0\nv2 := random 0xFF\nexit";
+
+    let first = run(source, Platform::CosmacVip);
+
+    let second = run(source, Platform::CosmacVip);
+
+    assert!(register(&first, 0) <= 0x0F);
+
+    assert_eq!(register(&first, 1), 0);
+
+    assert_eq!(first.cpu().registers(), second.cpu().registers());
+}
+
+#[test]
+fn vip_random_numbers_come_from_the_interpreter_code() {
+    let mut machine = machine(
+        "
+        v0 := random 0xFF v1 := random 0xFF v2 := random 0xFF v3 := random 0xFF
+        v4 := random 0xFF v5 := random 0xFF v6 := random 0xFF v7 := random 0xFF
+        exit
+        ",
+        Platform::CosmacVip,
+    );
+
+    machine.cpu_mut().set_rng(Rng::new(0, RngAlgorithm::Vip));
+
+    let machine = finish(machine);
+
+    // The page starts with four zero bytes, before the code the rest come from
+    assert_eq!(
+        &machine.cpu().registers()[..8],
+        &[0x00, 0x00, 0x00, 0x00, 0x67, 0x8F, 0xBA, 0x98]
+    );
+}
+
+#[test]
+fn index_and_memory() {
+    let machine = run(
+        "
+        v0 := 254
+        i := data
+        bcd v0
+        load v2
+        v3 := 0xA
+        i := hex v3
+        exit
+
+        : data
+          0 0 0
+        ",
+        Platform::CosmacVip,
+    );
+
+    assert_eq!(&machine.cpu().registers()[..3], &[2, 5, 4]);
+
+    assert_eq!(machine.cpu().index(), 0xA * 5);
+
+    let machine = run("i := 0x300\nv0 := 0x10\ni += v0\nexit", Platform::CosmacVip);
+
+    assert_eq!(machine.cpu().index(), 0x310);
+}
+
+#[test]
+fn memory_is_limited_to_the_platform() {
+    // Two bytes from 0xFFE fit exactly, a third runs past the end of 4KiB
+    run("i := 0xFFE\nsave v1\nexit", Platform::SuperChip);
+
+    let e = fault("i := 0xFFE\nsave v2\nexit", Platform::SuperChip);
+
+    assert!(
+        matches!(e, EmulatorError::MemoryOutOfBounds { addr: 0x1000, .. }),
+        "{}",
+        e
+    );
+
+    let machine = run("i := 0xFFE\nsave v2\nexit", Platform::XoChip);
+
+    assert_eq!(machine.cpu().index(), 0x1001);
+
+    let mut machine = Machine::new();
+
+    let e = machine.load_rom(&[0; 0xE01]).unwrap_err();
+
+    assert!(
+        matches!(e, EmulatorError::RomTooLarge { max: 0xE00, .. }),
+        "{}",
+        e
+    );
+
+    // Loading straight into the CPU is held to the same limit
+    let e = machine.cpu_mut().load(&[0; MEM_SIZE]).unwrap_err();
+
+    assert!(
+        matches!(e, EmulatorError::RomTooLarge { size: MEM_SIZE, .. }),
+        "{}",
+        e
+    );
+}
+
+#[test]
+fn flags_storage() {
+    let machine = run(
+        "
+        v0 := 1
+        v1 := 2
+        saveflags v1
+        v0 := 0
+        v1 := 0
+        loadflags v1
+        exit
+        ",
+        Platform::SuperChip,
+    );
+
+    assert_eq!(&machine.cpu().registers()[..2], &[1, 2]);
+
+    assert_eq!(&machine.cpu().flags()[..2], &[1, 2]);
+}
+
+#[test]
+fn xo_chip_audio_and_long_index() {
+    let machine = run(
+        "
+        i := pattern
+        audio
+        v0 := 100
+        pitch := v0
+        i := long 0x1234
+        exit
+
+        : pattern
+          0x00 0x11 0x22 0x33 0x44 0x55 0x66 0x77
+          0x88 0x99 0xAA 0xBB 0xCC 0xDD 0xEE 0xFF
+        ",
+        Platform::XoChip,
+    );
+
+    let pattern = machine
+        .cpu()
+        .audio_pattern()
+        .expect("audio loads a pattern");
+
+    assert_eq!(pattern[1], 0x11);
+
+    assert_eq!(pattern[15], 0xFF);
+
+    assert_eq!(machine.cpu().pitch(), 100);
+
+    assert_eq!(machine.cpu().index(), 0x1234);
+}
+
+// Moves a random digit with keys 5 and 6, keeping the registers above 4KiB
+const MOVIE_ROM: &str = "
+loop
+  v0 := 5
+  if v0 key then v2 += 1
+  v0 := 6
+  if v0 key then v3 += 1
+  i := long 0x1000
+  save v3
+  clear
+  v0 := random 0xF
+  i := hex v0
+  sprite v2 v3 5
+again
+";
+
+#[test]
+fn movies_replay_to_the_same_display() {
+    let mut machine = machine(MOVIE_ROM, Platform::XoChip);
+
+    let mut movie = Movie::new(&machine, Platform::XoChip);
+
+    for frame in 0..60 {
+        let mut keys = [false; NUM_KEYS];
+
+        keys[5] = (10..30).contains(&frame);
+
+        keys[6] = (20..50).contains(&frame);
+
+        machine.set_keys(&keys);
+
+        movie.record(machine.frames(), machine.cpu().keys());
+
+        machine.run_frame().unwrap();
+    }
+
+    let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+
+    assert_eq!(movie.len(), 60);
+
+    // A fresh machine, with nothing set up but the ROM
+    let mut replay = Machine::new();
+
+    replay.load_rom(&assemble(MOVIE_ROM)).unwrap();
+
+    movie.apply(&mut replay).unwrap();
+
+    while let Some(keys) = movie.keys(replay.frames()) {
+        replay.set_keys(&keys);
+
+        replay.run_frame().unwrap();
+    }
+
+    assert_eq!(replay.frames(), machine.frames());
+
+    assert_eq!(replay.cpu().registers(), machine.cpu().registers());
+
+    assert_eq!(replay.display().to_ascii(), machine.display().to_ascii());
+
+    let mut other = Machine::new();
+
+    other.load_rom(&assemble("exit")).unwrap();
+
+    let e = movie.apply(&mut other).unwrap_err();
+
+    assert!(matches!(e, EmulatorError::InvalidMovie(_)), "{}", e);
+}
+
+#[test]
+fn movies_must_be_whole() {
+    let mut movie = Movie::new(&machine("exit", Platform::CosmacVip), Platform::CosmacVip);
+
+    movie.record(2, &[true; NUM_KEYS]);
+
+    let bytes = movie.to_bytes();
+
+    assert_eq!(Movie::from_bytes(&bytes).unwrap(), movie);
+
+    let mut bad_magic = bytes.clone();
+
+    bad_magic[0] = b'X';
+
+    let mut bad_version = bytes.clone();
+
+    bad_version[4] = 0xFF;
+
+    for bytes in [
+        &bytes[..bytes.len() - 1],
+        &bytes[..10],
+        &bad_magic,
+        &bad_version,
+    ] {
+        let e = Movie::from_bytes(bytes).unwrap_err();
+
+        assert!(matches!(e, EmulatorError::InvalidMovie(_)), "{}", e);
+    }
+}
+
+#[test]
+fn disassembly_round_trips_through_the_assembler() {
+    let roms = [
+        FONT_ROM,
+        BIG_FONT_ROM,
+        LORES_ROM,
+        COLLISIONS_ROM,
+        EDGES_ROM,
+        SCROLL_ROM,
+        PLANES_ROM,
+        MOVIE_ROM,
+    ];
+
+    for source in &roms {
+        let rom = assemble(source);
+
+        let listing = mushypeas::disassemble(&rom, Syntax::Octo);
+
+        let reassembled =
+            mushypeas::assemble(&listing).unwrap_or_else(|e| panic!("{}\n{}", e, listing));
+
+        assert!(rom == reassembled, "{} doesn't reassemble", listing);
+    }
+}
+
+#[test]
+fn assembler_errors_give_the_line() {
+    let cases = [
+        ("v0 := 1\njump nowhere", 2, "undefined name 'nowhere'"),
+        ("v0 := 1\n\nv1 := 300", 3, "300 doesn't fit in a byte"),
+        (
+            "v0 := 1\nv1 += bogus",
+            2,
+            "expected a number, found 'bogus'",
+        ),
+        (":org 0x100\nexit", 1, ":org 0x100 is before 0x200"),
+        (
+            ":macro add a b { a += b }\nadd v0",
+            2,
+            "macro 'add' takes 2 arguments",
+        ),
+    ];
+
+    for &(source, line, message) in &cases {
+        match mushypeas::assemble(source) {
+            Err(EmulatorError::AsmError {
+                line: at,
+                message: found,
+            }) => assert_eq!((at, found.as_str()), (line, message), "{}", source),
+            other => panic!("{:?} from {}", other.map(|_| ()), source),
+        }
+    }
+}
+
+// Counts frames in v0 and draws a digit that moves with them
+const COUNTER_ROM: &str = "
+loop
+  v0 += 1
+  i := hex v0
+  sprite v0 v0 5
+again
+";
+
+#[test]
+fn save_states_restore_the_machine() {
+    let mut machine = machine(COUNTER_ROM, Platform::CosmacVip);
+
+    machine.run(35).unwrap();
+
+    let state = machine.save_state();
+
+    let saved = machine.clone();
+
+    machine.run(50).unwrap();
+
+    machine.load_state(&state).unwrap();
+
+    assert_eq!(machine.frames(), saved.frames());
+
+    assert_eq!(machine.cpu().pc(), saved.cpu().pc());
+
+    assert_eq!(machine.cpu().registers(), saved.cpu().registers());
+
+    assert_eq!(machine.display().to_ascii(), saved.display().to_ascii());
+
+    // Running on from the state goes the same way as running on from the save
+    let mut resumed = saved;
+
+    resumed.run(50).unwrap();
+
+    machine.run(50).unwrap();
+
+    assert_eq!(machine.save_state(), resumed.save_state());
+}
+
+#[test]
+fn save_states_are_refused_for_other_versions_and_roms() {
+    let mut machine = machine(COUNTER_ROM, Platform::CosmacVip);
+
+    machine.run(35).unwrap();
+
+    let state = machine.save_state();
+
+    let mut bad_version = state.clone();
+
+    bad_version[4] = 0xFF;
+
+    let mut bad_magic = state.clone();
+
+    bad_magic[0] = b'X';
+
+    let before = machine.save_state();
+
+    for bad in [&bad_version, &bad_magic, &state[..state.len() - 1]] {
+        let e = machine.load_state(bad).unwrap_err();
+
+        assert!(matches!(e, EmulatorError::InvalidSaveState(_)), "{}", e);
+
+        assert_eq!(machine.save_state(), before);
+    }
+
+    let mut other = self::machine("exit", Platform::CosmacVip);
+
+    let e = other.load_state(&state).unwrap_err();
+
+    assert!(matches!(e, EmulatorError::InvalidSaveState(_)), "{}", e);
+}
+
+#[test]
+fn rewinding_steps_back_a_frame_at_a_time() {
+    let mut machine = machine(COUNTER_ROM, Platform::CosmacVip);
+
+    let mut rewind = RewindBuffer::new(4);
+
+    let mut states = Vec::new();
+
+    for _ in 0..6 {
+        machine.run_frame().unwrap();
+
+        rewind.push(&machine);
+
+        states.push(machine.save_state());
+    }
+
+    // Only the newest four are kept, and the newest is where the machine already is
+    assert_eq!(rewind.len(), 4);
+
+    for expected in states[2..5].iter().rev() {
+        assert!(rewind.rewind(&mut machine));
+
+        assert!(machine.save_state() == *expected);
+    }
+
+    assert!(!rewind.rewind(&mut machine));
+
+    assert!(machine.save_state() == states[2]);
+
+    // Running on from a rewound frame picks up from there
+    machine.run_frame().unwrap();
+
+    rewind.push(&machine);
+
+    assert!(machine.save_state() == states[3]);
+
+    assert!(rewind.rewind(&mut machine));
+
+    assert!(machine.save_state() == states[2]);
+}